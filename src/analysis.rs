@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+
+use crate::node::{Item, ItemKind, Node, NodeKind};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Diagnostics {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+// Names which are always available, without being defined by the program
+const MAGIC_NAMES: &[&str] = &["$out", "$in", "$index", "$arg", "$peers"];
+
+pub fn analyse(items: &[Item]) -> Diagnostics {
+    let mut diagnostics = Diagnostics::default();
+
+    // Check for duplicate task and constant names
+    let mut task_names = HashSet::new();
+    let mut constant_names = HashSet::new();
+    for item in items {
+        match &item.kind {
+            ItemKind::TaskDefinition { name, .. } => {
+                if !task_names.insert(name.as_str()) || constant_names.contains(name.as_str()) {
+                    diagnostics.errors.push(format!("task `{name}` is defined more than once"));
+                }
+            }
+            ItemKind::ConstantDefinition { name, .. } => {
+                if !constant_names.insert(name.as_str()) || task_names.contains(name.as_str()) {
+                    diagnostics.errors.push(format!("constant `{name}` is defined more than once"));
+                }
+            }
+        }
+    }
+
+    for item in items {
+        let ItemKind::TaskDefinition { name: task_name, body, alternates, notify, .. } = &item.kind else { continue };
+
+        // Find out which locals the task defines, and which names it reads
+        let mut assigned = vec![];
+        let mut read = HashSet::new();
+        collect_names(body, &mut assigned, &mut read);
+
+        // Check that every channel refers to something which exists
+        let mut channels = vec![];
+        collect_channels(body, &mut channels);
+        for channel in channels {
+            if !task_names.contains(channel.as_str())
+                && !assigned.contains(&channel)
+                && !MAGIC_NAMES.contains(&channel.as_str())
+            {
+                diagnostics.errors.push(format!("task `{task_name}` uses unknown channel `{channel}`"));
+            }
+        }
+
+        let mut reported = HashSet::new();
+        for local in &assigned {
+            if !reported.insert(local) {
+                continue;
+            }
+
+            // Locals take priority over tasks when resolving names, so a local with the same name
+            // as a task hides it - sends to that name would go to whatever the local contains
+            if task_names.contains(local.as_str()) {
+                diagnostics.warnings.push(format!("variable `{local}` in task `{task_name}` shadows the task of the same name"));
+            }
+
+            // Check that every local is used - names starting with an underscore are deliberately
+            // unused
+            if !read.contains(local) && !local.starts_with('_') {
+                diagnostics.warnings.push(format!("variable `{local}` in task `{task_name}` is never used"));
+            }
+        }
+
+        for peer in notify {
+            if !task_names.contains(peer.as_str()) {
+                diagnostics.errors.push(format!("task `{task_name}` notifies unknown task `{peer}`"));
+            }
+        }
+
+        // Check that the task takes turns sending to and receiving from the peers it says it does
+        for peer in alternates {
+            if !task_names.contains(peer.as_str()) {
+                diagnostics.errors.push(format!("task `{task_name}` alternates with unknown task `{peer}`"));
+                continue;
+            }
+
+            let mut violation = None;
+            check_alternation(body, peer, &mut None, &mut violation);
+            match violation {
+                Some(Direction::Send) => diagnostics.warnings.push(format!(
+                    "task `{task_name}` can send to `{peer}` twice without receiving in between")),
+                Some(Direction::Receive) => diagnostics.warnings.push(format!(
+                    "task `{task_name}` can receive from `{peer}` twice without sending in between")),
+                None => (),
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn collect_names(node: &Node, assigned: &mut Vec<String>, read: &mut HashSet<String>) {
+    match &node.kind {
+        NodeKind::Identifier(name) => { read.insert(name.clone()); },
+
+        NodeKind::Assign { value, destination } => {
+            collect_assigned(destination, assigned, read);
+            collect_names(value, assigned, read);
+        }
+
+        NodeKind::Receive { value, channel, bind_channel, default, timeout, .. } => {
+            collect_assigned(value, assigned, read);
+            if *bind_channel {
+                collect_assigned(channel, assigned, read);
+            } else {
+                collect_names(channel, assigned, read);
+            }
+            if let Some(default) = default {
+                collect_names(default, assigned, read);
+            }
+            if let Some(timeout) = timeout {
+                collect_names(timeout, assigned, read);
+            }
+        }
+
+        NodeKind::Lambda { parameter, body } => {
+            assigned.push(parameter.clone());
+            collect_names(body, assigned, read);
+        }
+
+        NodeKind::For { binding, iterable, body, .. } => {
+            collect_names(iterable, assigned, read);
+            assigned.push(binding.clone());
+            collect_names(body, assigned, read);
+        }
+
+        NodeKind::Try { body, handler, binding } => {
+            collect_names(body, assigned, read);
+            if let Some(binding) = binding {
+                assigned.push(binding.clone());
+            }
+            collect_names(handler, assigned, read);
+        }
+
+        _ => for child in node.children() {
+            collect_names(child, assigned, read);
+        }
+    }
+}
+
+fn collect_assigned(node: &Node, assigned: &mut Vec<String>, read: &mut HashSet<String>) {
+    match &node.kind {
+        NodeKind::Identifier(name) => assigned.push(name.clone()),
+
+        // Destructuring assigns every name in the pattern
+        NodeKind::ArrayLiteral(patterns) => for pattern in patterns {
+            collect_assigned(pattern, assigned, read);
+        },
+        NodeKind::Range { begin: Some(rest), end: None } => collect_assigned(rest, assigned, read),
+
+        _ => collect_names(node, assigned, read),
+    }
+}
+
+fn collect_channels(node: &Node, channels: &mut Vec<String>) {
+    match &node.kind {
+        NodeKind::Send { channel, .. } | NodeKind::Receive { channel, bind_channel: false, .. } => {
+            if let NodeKind::Identifier(name) = &channel.kind {
+                channels.push(name.clone());
+            }
+        }
+
+        _ => (),
+    }
+
+    for child in node.children() {
+        collect_channels(child, channels);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Send,
+    Receive,
+}
+
+/// Walks the sends to and receives from `peer` in the order they could happen, tracking which
+/// happened `last`, and noting the first time the same one happens twice in a row.
+fn check_alternation(node: &Node, peer: &str, last: &mut Option<Direction>, violation: &mut Option<Direction>) {
+    let is_peer = |channel: &Node| matches!(&channel.kind, NodeKind::Identifier(name) if name == peer);
+    fn record(direction: Direction, last: &mut Option<Direction>, violation: &mut Option<Direction>) {
+        if *last == Some(direction) && violation.is_none() {
+            *violation = Some(direction);
+        }
+        *last = Some(direction);
+    }
+
+    match &node.kind {
+        NodeKind::Send { value, channel, .. } => {
+            check_alternation(value, peer, last, violation);
+            if is_peer(channel) {
+                record(Direction::Send, last, violation);
+            }
+        }
+
+        NodeKind::Receive { channel, bind_channel: false, .. } if is_peer(channel) => {
+            record(Direction::Receive, last, violation);
+        }
+
+        // Go round twice, to catch a loop which doesn't alternate between one iteration and the
+        // next
+        NodeKind::While { condition, body, .. } => {
+            for _ in 0..2 {
+                check_alternation(condition, peer, last, violation);
+                check_alternation(body, peer, last, violation);
+            }
+        }
+
+        NodeKind::For { iterable, body, .. } => {
+            check_alternation(iterable, peer, last, violation);
+            for _ in 0..2 {
+                check_alternation(body, peer, last, violation);
+            }
+        }
+
+        // Either branch could run, and if they end differently, we don't know what happened last
+        NodeKind::If { condition, if_true, if_false } => {
+            check_alternation(condition, peer, last, violation);
+            let mut last_if_true = *last;
+            check_alternation(if_true, peer, &mut last_if_true, violation);
+            if let Some(if_false) = if_false {
+                check_alternation(if_false, peer, last, violation);
+            }
+            if last_if_true != *last {
+                *last = None;
+            }
+        }
+
+        // These run elsewhere, or maybe not at all
+        NodeKind::AnonymousTask(_) | NodeKind::Lambda { .. } => (),
+
+        _ => for child in node.children() {
+            check_alternation(child, peer, last, violation);
+        }
+    }
+}
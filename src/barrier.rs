@@ -0,0 +1,72 @@
+use std::{sync::{Condvar, Mutex}, time::Duration};
+
+use crate::interpreter::{Globals, InterpreterError, InterpreterErrorKind, TaskState};
+
+/// Where the instances of a multi-task wait for each other with `barrier`.
+///
+/// Unlike `std::sync::Barrier`, waiting stops with an error if the task is cancelled, or if another
+/// instance finishes - it can't reach the barrier any more, so the rest would otherwise wait
+/// forever.
+#[derive(Debug)]
+pub struct TaskBarrier {
+    count: usize,
+    state: Mutex<BarrierState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct BarrierState {
+    waiting: usize,
+
+    /// Increases each time every instance reaches the barrier, releasing the ones waiting.
+    generation: usize,
+
+    finished: usize,
+}
+
+impl TaskBarrier {
+    pub fn new(count: usize) -> Self {
+        Self { count, state: Mutex::new(BarrierState::default()), changed: Condvar::new() }
+    }
+
+    /// Waits until every instance has reached the barrier.
+    pub fn wait(&self, globals: &Globals) -> Result<(), InterpreterError> {
+        // Cancellation and the deadline aren't signalled through the condvar, so check regularly
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let mut state = self.state.lock().unwrap();
+        let generation = state.generation;
+        state.waiting += 1;
+        if state.waiting == self.count {
+            state.waiting = 0;
+            state.generation += 1;
+            self.changed.notify_all();
+            return Ok(())
+        }
+
+        loop {
+            if state.generation != generation {
+                return Ok(())
+            }
+
+            let error = if state.finished > 0 {
+                Some(InterpreterError::with_kind(InterpreterErrorKind::ChannelClosed,
+                    "another instance finished without reaching the barrier"))
+            } else {
+                TaskState::check_cancelled(globals).err()
+            };
+            if let Some(error) = error {
+                state.waiting -= 1;
+                return Err(error)
+            }
+
+            state = self.changed.wait_timeout(state, POLL_INTERVAL).unwrap().0;
+        }
+    }
+
+    /// Records that an instance has finished, so it won't reach the barrier again.
+    pub fn finish(&self) {
+        self.state.lock().unwrap().finished += 1;
+        self.changed.notify_all();
+    }
+}
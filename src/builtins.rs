@@ -0,0 +1,537 @@
+use std::{cmp::Ordering, collections::HashSet, time::Duration};
+
+use crossbeam_channel::Select;
+
+use crate::{json, interpreter::{TaskState, Globals, Value, InterpreterError, InterpreterErrorKind}, node::{Node, NodeKind}};
+
+impl TaskState {
+    pub(crate) fn call_builtin(&mut self, name: &str, arguments: &[Node], globals: &Globals) -> Result<Value, InterpreterError> {
+        match name {
+            // On strings, these work with characters, which are strings of their own
+            "head" => {
+                let [value] = self.evaluate_arguments(name, arguments, globals)?;
+
+                match value {
+                    Value::Array(items) => items.into_iter().next(),
+                    Value::String(s) => s.chars().next().map(|c| Value::String(c.to_string())),
+                    _ => return Err(InterpreterError::new(format!("`head` expected an array or string, but found {}", value.to_printable_string()))),
+                }.ok_or_else(|| InterpreterError::new("`head` called on an empty array or string"))
+            }
+
+            "tail" => {
+                let [value] = self.evaluate_arguments(name, arguments, globals)?;
+
+                match value {
+                    Value::Array(items) => Ok(Value::Array(items.into_iter().skip(1).collect())),
+                    Value::String(s) => Ok(Value::String(s.chars().skip(1).collect())),
+                    _ => Err(InterpreterError::new(format!("`tail` expected an array or string, but found {}", value.to_printable_string()))),
+                }
+            }
+
+            "last" => {
+                let [value] = self.evaluate_arguments(name, arguments, globals)?;
+
+                match value {
+                    Value::Array(items) => items.into_iter().last(),
+                    Value::String(s) => s.chars().last().map(|c| Value::String(c.to_string())),
+                    _ => return Err(InterpreterError::new(format!("`last` expected an array or string, but found {}", value.to_printable_string()))),
+                }.ok_or_else(|| InterpreterError::new("`last` called on an empty array or string"))
+            }
+
+            "sort" => {
+                let [arr] = self.evaluate_arguments(name, arguments, globals)?;
+                let mut items = arr.get_array()?.clone();
+
+                // `sort_by` can't fail, so hold onto the first comparison error
+                let mut error = None;
+                items.sort_by(|l, r| l.compare(r).unwrap_or_else(|e| {
+                    error.get_or_insert(e);
+                    Ordering::Equal
+                }));
+
+                match error {
+                    Some(e) => Err(e),
+                    None => Ok(Value::Array(items)),
+                }
+            }
+
+            "flatten" => {
+                let [arr] = self.evaluate_arguments(name, arguments, globals)?;
+
+                let mut items = vec![];
+                for inner in arr.get_array()? {
+                    let Value::Array(inner) = inner else {
+                        return Err(InterpreterError::new(format!("`flatten` expected an array of arrays, but found {}", inner.to_printable_string())))
+                    };
+                    items.extend(inner.iter().cloned());
+                }
+
+                Ok(Value::Array(items))
+            }
+
+            "fill" => {
+                let [value, count] = self.evaluate_arguments(name, arguments, globals)?;
+                let count = count.get_integer()?;
+                if count < 0 {
+                    return Err(InterpreterError::new(format!("`fill` count cannot be negative, but got {count}")))
+                }
+
+                // Check before allocating, since a huge count would otherwise exhaust memory
+                self.check_allocation((count as usize).saturating_mul(value.size() + 1), globals)?;
+
+                Ok(Value::Array(vec![value; count as usize]))
+            }
+
+            "unique" => {
+                let [arr] = self.evaluate_arguments(name, arguments, globals)?;
+
+                // Floats (and anything containing them) can't go in a set, since NaN isn't equal
+                // to itself, so those are compared with each one kept so far instead
+                let mut items: Vec<Value> = vec![];
+                let mut seen = HashSet::new();
+                let mut unhashable: Vec<&Value> = vec![];
+                for item in arr.get_array()? {
+                    let new = if item.is_hashable() {
+                        seen.insert(HashableValue(item))
+                    } else if unhashable.contains(&item) {
+                        false
+                    } else {
+                        unhashable.push(item);
+                        true
+                    };
+                    if new {
+                        items.push(item.clone());
+                    }
+                }
+
+                Ok(Value::Array(items))
+            }
+
+            "insert" => {
+                let [arr, index, item] = self.get_arguments(name, arguments)?;
+                let local = Self::get_local_name(name, arr)?;
+                let index = self.evaluate(index, globals)?.get_integer()?;
+                let item = self.evaluate(item, globals)?;
+
+                let mut items = self.get_local(local)?.get_array()?.clone();
+
+                // Inserting at the length appends to the end
+                let wrapped = Self::wrap_as_index(index, items.len());
+                if wrapped > items.len() {
+                    return Err(InterpreterError::new(format!("index {index} is out of range")))
+                }
+                items.insert(wrapped, item);
+                let items = Value::Array(items);
+                self.allocate(items.size(), globals)?;
+                self.assign_local(local, items, globals);
+
+                Ok(Value::Null)
+            }
+
+            "remove" => {
+                let [arr, index] = self.get_arguments(name, arguments)?;
+                let local = Self::get_local_name(name, arr)?;
+                let index = self.evaluate(index, globals)?;
+
+                let mut items = self.get_local(local)?.get_array()?.clone();
+
+                // Evaluates to whatever was removed
+                let removed = match index {
+                    Value::Integer(index) => {
+                        let wrapped = Self::wrap_as_index(index, items.len());
+                        if wrapped >= items.len() {
+                            return Err(InterpreterError::new(format!("index {index} is out of range")))
+                        }
+                        items.remove(wrapped)
+                    }
+
+                    Value::Range { begin, end } => {
+                        let (begin_val, end_val) = Self::slice_bounds(&begin, &end, items.len())?;
+                        if begin_val > end_val || end_val > items.len() {
+                            return Err(InterpreterError::new(format!("indeces {} .. {} are out of range",
+                                begin.to_printable_string(), end.to_printable_string())))
+                        }
+                        Value::Array(items.drain(begin_val..end_val).collect())
+                    }
+
+                    _ => return Err(InterpreterError::new("expected integer or range as index")),
+                };
+                self.assign_local(local, Value::Array(items), globals);
+
+                Ok(removed)
+            }
+
+            "swap" => {
+                // Either swaps two elements of an array variable, or two variables
+                if arguments.len() == 3 {
+                    let [arr, i, j] = self.get_arguments(name, arguments)?;
+                    let local = Self::get_local_name(name, arr)?;
+                    let i = self.evaluate(i, globals)?.get_integer()?;
+                    let j = self.evaluate(j, globals)?.get_integer()?;
+
+                    let mut items = self.get_local(local)?.get_array()?.clone();
+                    let (wrapped_i, wrapped_j) = (Self::wrap_as_index(i, items.len()), Self::wrap_as_index(j, items.len()));
+                    for (index, wrapped) in [(i, wrapped_i), (j, wrapped_j)] {
+                        if wrapped >= items.len() {
+                            return Err(InterpreterError::new(format!("index {index} is out of range")))
+                        }
+                    }
+                    items.swap(wrapped_i, wrapped_j);
+                    self.assign_local(local, Value::Array(items), globals);
+                } else {
+                    let [a, b] = self.get_arguments(name, arguments)?;
+                    let (a, b) = (Self::get_local_name(name, a)?, Self::get_local_name(name, b)?);
+                    let a_value = self.get_local(a)?.clone();
+                    let b_value = self.get_local(b)?.clone();
+                    self.assign_local(a, b_value, globals);
+                    self.assign_local(b, a_value, globals);
+                }
+
+                Ok(Value::Null)
+            }
+
+            "reverse" => {
+                let [value] = self.evaluate_arguments(name, arguments, globals)?;
+
+                match value {
+                    Value::Array(items) => Ok(Value::Array(items.into_iter().rev().collect())),
+                    Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+                    _ => Err(InterpreterError::new(format!("`reverse` expected an array or string, but found {}", value.to_printable_string()))),
+                }
+            }
+
+            "clamp" => {
+                let [x, lo, hi] = self.evaluate_arguments(name, arguments, globals)?;
+
+                // Stay as integers if we can, otherwise treat everything as floats
+                if let (Value::Integer(x), Value::Integer(lo), Value::Integer(hi)) = (&x, &lo, &hi) {
+                    if lo > hi {
+                        return Err(InterpreterError::new(format!("`clamp` lower bound {lo} is greater than upper bound {hi}")))
+                    }
+                    return Ok(Value::Integer(*x.clamp(lo, hi)))
+                }
+
+                let (x, lo, hi) = (x.get_float()?, lo.get_float()?, hi.get_float()?);
+                if lo > hi || lo.is_nan() || hi.is_nan() {
+                    return Err(InterpreterError::new(format!("`clamp` lower bound {lo} is greater than upper bound {hi}")))
+                }
+                Ok(Value::Float(x.clamp(lo, hi)))
+            }
+
+            "count" => {
+                let [arr, target] = self.get_arguments(name, arguments)?;
+                let arr = self.evaluate(arr, globals)?;
+
+                // Count either the elements matching a lambda, or the elements equal to a value
+                let mut count = 0;
+                if let NodeKind::Lambda { parameter, body } = &target.kind {
+                    for item in arr.get_array()? {
+                        if self.call_lambda(parameter, body, item.clone(), globals)?.is_truthy() {
+                            count += 1;
+                        }
+                    }
+                } else {
+                    let target = self.evaluate(target, globals)?;
+                    count = arr.get_array()?.iter().filter(|item| **item == target).count();
+                }
+
+                Ok(Value::Integer(count as i64))
+            }
+
+            "index_of" => {
+                let [arr, target] = self.get_arguments(name, arguments)?;
+                let arr = self.evaluate(arr, globals)?;
+
+                // Like `count`, find either an element matching a lambda, or one equal to a value
+                let mut index = None;
+                if let NodeKind::Lambda { parameter, body } = &target.kind {
+                    for (i, item) in arr.get_array()?.iter().enumerate() {
+                        if self.call_lambda(parameter, body, item.clone(), globals)?.is_truthy() {
+                            index = Some(i);
+                            break;
+                        }
+                    }
+                } else {
+                    let target = self.evaluate(target, globals)?;
+                    index = arr.get_array()?.iter().position(|item| *item == target);
+                }
+
+                // Not -1, since that's a valid index meaning the last element
+                Ok(index.map(|i| Value::Integer(i as i64)).unwrap_or(Value::Null))
+            }
+
+            "gather" => {
+                let [tasks] = self.evaluate_arguments(name, arguments, globals)?;
+                let ids = tasks.get_array()?.iter()
+                    .map(|task| task.get_task_id())
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Take one value from each task in whichever order they arrive, but put them in
+                // the same order as the tasks
+                let mut results = vec![Value::Null; ids.len()];
+                let mut pending: Vec<_> = (0..ids.len()).collect();
+                while !pending.is_empty() {
+                    let receivers = pending.iter()
+                        .map(|i| self.get_receiver_from_task(&ids[*i]))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    let mut selector = Select::new();
+                    for receiver in &receivers {
+                        selector.recv(receiver);
+                    }
+                    let selected = selector.select();
+                    let selected_index = selected.index();
+                    let i = pending[selected_index];
+                    let value = selected.recv(receivers[selected_index])
+                        .map_err(|_| InterpreterError::with_kind(InterpreterErrorKind::ChannelClosed, format!("`gather` could not receive from {}, as it has finished",
+                            globals.task_description(&ids[i]))))?;
+
+                    results[i] = value;
+                    pending.retain(|p| *p != i);
+                }
+
+                Ok(Value::Array(results))
+            }
+
+            "tag" => {
+                let [value] = self.evaluate_arguments(name, arguments, globals)?;
+
+                match value {
+                    Value::Tagged { tag, .. } => Ok(Value::String(tag)),
+                    _ => Err(InterpreterError::new(format!("`tag` expected a tagged value, but found {}", value.to_printable_string()))),
+                }
+            }
+
+            "to_map" => {
+                let [pairs] = self.evaluate_arguments(name, arguments, globals)?;
+
+                // Later pairs replace earlier ones with the same key
+                let mut map: Vec<(Value, Value)> = vec![];
+                for pair in pairs.get_array()? {
+                    let Value::Array(pair) = pair else {
+                        return Err(InterpreterError::new(format!("`to_map` expected each pair to be an array, but found {}", pair.to_printable_string())))
+                    };
+                    let [key, value] = pair.as_slice() else {
+                        return Err(InterpreterError::new(format!("`to_map` expected each pair to have 2 elements, but one has {}", pair.len())))
+                    };
+                    if !key.is_hashable() {
+                        return Err(InterpreterError::new(format!("{} can't be used as a map key", key.to_printable_string())))
+                    }
+
+                    match map.iter_mut().find(|(k, _)| k == key) {
+                        Some((_, existing)) => *existing = value.clone(),
+                        None => map.push((key.clone(), value.clone())),
+                    }
+                }
+
+                Ok(Value::Map(map))
+            }
+
+            "to_pairs" => {
+                let [map] = self.evaluate_arguments(name, arguments, globals)?;
+                let Value::Map(pairs) = map else {
+                    return Err(InterpreterError::new(format!("`to_pairs` expected a map, but found {}", map.to_printable_string())))
+                };
+
+                Ok(Value::Array(pairs.into_iter().map(|(k, v)| Value::Array(vec![k, v])).collect()))
+            }
+
+            "sleep" => {
+                let [ms] = self.evaluate_arguments(name, arguments, globals)?;
+                let duration = match ms {
+                    Value::Integer(ms) if ms >= 0 => Duration::from_millis(ms as u64),
+                    _ => return Err(InterpreterError::new(format!("`sleep` expected a non-negative integer of milliseconds, but found {}", ms.to_printable_string()))),
+                };
+
+                // There's no point sleeping past the deadline, since the task will stop there
+                let duration = match globals.deadline {
+                    Some(deadline) => duration.min(deadline.saturating_duration_since(globals.clock.now())),
+                    None => duration,
+                };
+                globals.clock.sleep(duration);
+                Self::check_cancelled(globals)?;
+
+                Ok(Value::Null)
+            }
+
+            "parse_json" => {
+                let [text] = self.evaluate_arguments(name, arguments, globals)?;
+                let Value::String(text) = text else {
+                    return Err(InterpreterError::new(format!("`parse_json` expected a string, but found {}", text.to_printable_string())))
+                };
+
+                json::parse_json(&text).map_err(|e| InterpreterError::new(format!("`parse_json` found malformed JSON: {e}")))
+            }
+
+            "ref" => {
+                let [task_name] = self.evaluate_arguments(name, arguments, globals)?;
+                let Value::String(task_name) = task_name else {
+                    return Err(InterpreterError::new(format!("`ref` expected a string, but found {}", task_name.to_printable_string())))
+                };
+
+                // Constants live alongside tasks, but aren't tasks, so shouldn't be found
+                let is_task = |value: &Value| matches!(value, Value::TaskReference(..));
+                match globals.task_values_by_name.get(&task_name) {
+                    Some(value) if is_task(value) => Ok(value.clone()),
+                    Some(Value::Array(items)) if items.iter().all(is_task) => Ok(Value::Array(items.clone())),
+                    _ => Ok(Value::Null),
+                }
+            }
+
+            "join" => {
+                let [parts, separator] = self.evaluate_arguments(name, arguments, globals)?;
+                let Value::String(separator) = separator else {
+                    return Err(InterpreterError::new(format!("`join` expected a string separator, but found {}", separator.to_printable_string())))
+                };
+
+                // Anything which isn't a string is joined as it would be printed
+                Ok(Value::String(parts.get_array()?.iter()
+                    .map(|part| part.to_printable_string())
+                    .collect::<Vec<_>>()
+                    .join(&separator)))
+            }
+
+            "format" => {
+                let Some((template, arguments)) = arguments.split_first() else {
+                    return Err(InterpreterError::new("`format` expects a template string"))
+                };
+                let template = self.evaluate(template, globals)?;
+                let Value::String(template) = template else {
+                    return Err(InterpreterError::new(format!("`format` expected a template string, but found {}", template.to_printable_string())))
+                };
+                let arguments = arguments.iter()
+                    .map(|arg| self.evaluate(arg, globals))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Each `{}` takes the next argument, as it would be printed
+                let mut result = String::new();
+                let mut remaining = arguments.iter();
+                let mut chars = template.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match (c, chars.peek()) {
+                        ('{', Some('{')) | ('}', Some('}')) => {
+                            chars.next();
+                            result.push(c);
+                        }
+                        ('{', Some('}')) => {
+                            chars.next();
+                            let Some(argument) = remaining.next() else {
+                                return Err(InterpreterError::new(format!("`format` template has more `{{}}` than the {} argument(s) given", arguments.len())))
+                            };
+                            result.push_str(&argument.to_printable_string());
+                        }
+                        ('{' | '}', _) => return Err(InterpreterError::new(format!("`format` template has an unmatched `{c}` - use `{c}{c}` for a literal one"))),
+                        _ => result.push(c),
+                    }
+                }
+                if remaining.next().is_some() {
+                    return Err(InterpreterError::new(format!("`format` was given {} argument(s), but its template uses fewer", arguments.len())))
+                }
+
+                Ok(Value::String(result))
+            }
+
+            "any" | "all" => {
+                let [arr, predicate] = self.get_arguments(name, arguments)?;
+                let arr = self.evaluate(arr, globals)?;
+                let (parameter, body) = Self::get_lambda(name, predicate)?;
+
+                // Stop as soon as the answer is known
+                let is_all = name == "all";
+                for item in arr.get_array()? {
+                    if self.call_lambda(parameter, body, item.clone(), globals)?.is_truthy() != is_all {
+                        return Ok(Value::Boolean(!is_all))
+                    }
+                }
+                Ok(Value::Boolean(is_all))
+            }
+
+            "fold" => {
+                let [arr, initial, body] = self.get_arguments(name, arguments)?;
+                let arr = self.evaluate(arr, globals)?;
+                let mut acc = self.evaluate(initial, globals)?;
+
+                // The body is evaluated with `acc` and `x` bound, putting back any locals they hide
+                let hidden = [self.locals.remove("acc"), self.locals.remove("x")];
+                let mut result = Ok(());
+                for item in arr.get_array()? {
+                    self.create_or_assign_local("acc", acc.clone());
+                    self.create_or_assign_local("x", item.clone());
+                    match self.evaluate(body, globals) {
+                        Ok(value) => acc = value,
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+
+                for (local, hidden) in ["acc", "x"].into_iter().zip(hidden) {
+                    self.locals.remove(local);
+                    if let Some(hidden) = hidden {
+                        self.locals.insert(local.to_string(), hidden);
+                    }
+                }
+                result.map(|_| acc)
+            }
+
+            _ => Err(InterpreterError::new(format!("no builtin named `{name}`"))),
+        }
+    }
+
+    fn evaluate_arguments<const N: usize>(&mut self, name: &str, arguments: &[Node], globals: &Globals) -> Result<[Value; N], InterpreterError> {
+        let arguments: [&Node; N] = self.get_arguments(name, arguments)?;
+
+        let values = arguments.iter()
+            .map(|arg| self.evaluate(arg, globals))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values.try_into().unwrap())
+    }
+
+    fn get_arguments<'n, const N: usize>(&self, name: &str, arguments: &'n [Node]) -> Result<[&'n Node; N], InterpreterError> {
+        if arguments.len() != N {
+            return Err(InterpreterError::new(format!("`{name}` expects {N} argument(s), but got {}", arguments.len())))
+        }
+
+        Ok(arguments.iter().collect::<Vec<_>>().try_into().unwrap())
+    }
+
+    fn get_local_name<'n>(name: &str, node: &'n Node) -> Result<&'n str, InterpreterError> {
+        // Builtins which modify an array need to know where to put it back
+        match &node.kind {
+            NodeKind::Identifier(local) => Ok(local),
+            _ => Err(InterpreterError::new(format!("`{name}` expects a variable as its first argument"))),
+        }
+    }
+
+    fn get_local(&self, local: &str) -> Result<&Value, InterpreterError> {
+        self.locals.get(local)
+            .ok_or_else(|| InterpreterError::new(format!("could not find variable `{local}`")))
+    }
+
+    fn get_lambda<'n>(name: &str, node: &'n Node) -> Result<(&'n str, &'n Node), InterpreterError> {
+        match &node.kind {
+            NodeKind::Lambda { parameter, body } => Ok((parameter, body)),
+            _ => Err(InterpreterError::new(format!("`{name}` expects a lambda, like `x => x > 0`"))),
+        }
+    }
+
+    fn call_lambda(&mut self, parameter: &str, body: &Node, argument: Value, globals: &Globals) -> Result<Value, InterpreterError> {
+        // Bind the parameter for the duration of the body, putting back any local it hides
+        let hidden = self.locals.remove(parameter);
+        self.create_or_assign_local(parameter, argument);
+        let result = self.evaluate(body, globals);
+
+        self.locals.remove(parameter);
+        if let Some(hidden) = hidden {
+            self.locals.insert(parameter.to_string(), hidden);
+        }
+        result
+    }
+}
+
+/// A value which `is_hashable`, so that it's always equal to itself and can be used in a `HashSet`.
+#[derive(PartialEq, Hash)]
+struct HashableValue<'a>(&'a Value);
+
+impl Eq for HashableValue<'_> {}
@@ -0,0 +1,223 @@
+use crate::{interpreter::{TaskState, Globals, Value, InterpreterError}, node::{Node, NodeKind, BinaryOperator, UnaryOperator}};
+
+/// An instruction for the stack machine which runs compiled task bodies. Every instruction which
+/// corresponds to an expression leaves exactly one value on the stack.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Push(Value),
+    Load(String),
+    /// Pops a value and assigns it to a local.
+    Store(String),
+    /// Pops this many values and pushes an array of them.
+    Array(usize),
+    BinaryOperation(BinaryOperator),
+    IsNull,
+    Pop,
+    Jump(usize),
+    /// Pops a value, and jumps if it isn't truthy.
+    JumpIfFalse(usize),
+    /// Evaluates a node with the tree-walking interpreter and pushes its result. This is used for
+    /// anything which isn't worth compiling, like channel operations, since they'll spend much
+    /// longer waiting than they would being interpreted.
+    Evaluate(Node),
+}
+
+/// A task body lowered to a flat list of instructions, so that hot loops don't need to walk the
+/// tree on every iteration.
+#[derive(Debug, Clone, Default)]
+pub struct Bytecode {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Bytecode {
+    pub fn compile(node: &Node) -> Self {
+        let mut bytecode = Self::default();
+        bytecode.compile_node(node);
+        bytecode
+    }
+
+    fn compile_node(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::Body(statements) => {
+                if statements.is_empty() {
+                    self.emit(Instruction::Push(Value::Null));
+                }
+
+                // Only the last statement's value is kept, as the value of the body
+                for (i, statement) in statements.iter().enumerate() {
+                    self.compile_node(statement);
+                    if i != statements.len() - 1 {
+                        self.emit(Instruction::Pop);
+                    }
+                }
+            }
+
+            NodeKind::IntegerLiteral(_) | NodeKind::FloatLiteral(_) | NodeKind::StringLiteral(_)
+            | NodeKind::BooleanLiteral(_) | NodeKind::NullLiteral
+                => self.emit(Instruction::Push(Value::from_literal(node).unwrap())),
+
+            NodeKind::ArrayLiteral(items) => {
+                for item in items {
+                    self.compile_node(item);
+                }
+                self.emit(Instruction::Array(items.len()));
+            }
+
+            NodeKind::Identifier(name) => self.emit(Instruction::Load(name.clone())),
+
+            // These short-circuit, so are compiled like `if`s which evaluate to `true` or `false`
+            NodeKind::BinaryOperation { left, op: op @ (BinaryOperator::And | BinaryOperator::Or), right } => {
+                self.compile_node(left);
+                let check_left = self.emit_placeholder();
+                let left_was_true = (*op == BinaryOperator::Or).then(|| self.emit_placeholder());
+
+                let right_start = self.instructions.len();
+                self.compile_node(right);
+                let check_right = self.emit_placeholder();
+
+                let true_start = self.instructions.len();
+                self.emit(Instruction::Push(Value::Boolean(true)));
+                let jump_to_end = self.emit_placeholder();
+                let false_start = self.instructions.len();
+                self.emit(Instruction::Push(Value::Boolean(false)));
+
+                // A false left side means `&&` is false, but `||` depends on the right side
+                let left_false_target = if *op == BinaryOperator::And { false_start } else { right_start };
+                self.patch(check_left, Instruction::JumpIfFalse(left_false_target));
+                if let Some(left_was_true) = left_was_true {
+                    self.patch(left_was_true, Instruction::Jump(true_start));
+                }
+                self.patch(check_right, Instruction::JumpIfFalse(false_start));
+                self.patch(jump_to_end, Instruction::Jump(self.instructions.len()));
+            }
+
+            NodeKind::BinaryOperation { left, op, right } => {
+                self.compile_node(left);
+                self.compile_node(right);
+                self.emit(Instruction::BinaryOperation(*op));
+            }
+
+            NodeKind::UnaryOperation { op: UnaryOperator::IsNull, value } => {
+                self.compile_node(value);
+                self.emit(Instruction::IsNull);
+            }
+
+            NodeKind::UnaryOperation { op: UnaryOperator::Not, value } => {
+                self.compile_node(value);
+                let jump_to_true = self.emit_placeholder();
+                self.emit(Instruction::Push(Value::Boolean(false)));
+                let jump_to_end = self.emit_placeholder();
+                self.patch(jump_to_true, Instruction::JumpIfFalse(self.instructions.len()));
+                self.emit(Instruction::Push(Value::Boolean(true)));
+                self.patch(jump_to_end, Instruction::Jump(self.instructions.len()));
+            }
+
+            NodeKind::Assign { value, destination } if matches!(destination.kind, NodeKind::Identifier(_)) => {
+                let NodeKind::Identifier(name) = &destination.kind else { unreachable!() };
+                self.compile_node(value);
+                self.emit(Instruction::Store(name.clone()));
+                self.emit(Instruction::Push(Value::Null));
+            }
+
+            NodeKind::If { condition, if_true, if_false } => {
+                self.compile_node(condition);
+                let jump_to_false = self.emit_placeholder();
+                self.compile_node(if_true);
+                let jump_to_end = self.emit_placeholder();
+
+                self.patch(jump_to_false, Instruction::JumpIfFalse(self.instructions.len()));
+                match if_false {
+                    Some(if_false) => self.compile_node(if_false),
+                    None => self.emit(Instruction::Push(Value::Null)),
+                }
+                self.patch(jump_to_end, Instruction::Jump(self.instructions.len()));
+            }
+
+            // `break` and `continue` are left to the interpreter, which knows how to unwind them
+            NodeKind::While { condition, body, .. } if !Self::contains_loop_exit(body) => {
+                // The loop evaluates to its body's last value, so keep that on the stack, replacing
+                // it each iteration
+                self.emit(Instruction::Push(Value::Null));
+
+                let start = self.instructions.len();
+                self.compile_node(condition);
+                let jump_to_end = self.emit_placeholder();
+                self.emit(Instruction::Pop);
+                self.compile_node(body);
+                self.emit(Instruction::Jump(start));
+
+                self.patch(jump_to_end, Instruction::JumpIfFalse(self.instructions.len()));
+            }
+
+            _ => self.emit(Instruction::Evaluate(node.clone())),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
+    fn emit_placeholder(&mut self) -> usize {
+        self.emit(Instruction::Jump(usize::MAX));
+        self.instructions.len() - 1
+    }
+
+    fn patch(&mut self, index: usize, instruction: Instruction) {
+        self.instructions[index] = instruction;
+    }
+
+    fn contains_loop_exit(node: &Node) -> bool {
+        matches!(node.kind, NodeKind::Break(_) | NodeKind::Continue(_))
+            || node.children().into_iter().any(Self::contains_loop_exit)
+    }
+}
+
+impl TaskState {
+    pub fn run_bytecode(&mut self, bytecode: &Bytecode, globals: &Globals) -> Result<Value, InterpreterError> {
+        let mut stack = vec![];
+        let mut pc = 0;
+
+        while let Some(instruction) = bytecode.instructions.get(pc) {
+            pc += 1;
+            Self::check_cancelled(globals)?;
+
+            match instruction {
+                Instruction::Push(value) => stack.push(value.clone()),
+                Instruction::Load(name) => stack.push(self.resolve(name, globals)?),
+                Instruction::Store(name) => {
+                    let value = stack.pop().unwrap();
+                    self.assign_local(name, value, globals);
+                }
+                Instruction::Array(length) => {
+                    let items = Value::Array(stack.split_off(stack.len() - length));
+                    self.allocate(items.size(), globals)?;
+                    stack.push(items);
+                }
+
+                Instruction::BinaryOperation(op) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    let result = Self::binary_operation(*op, left, right)?;
+                    self.allocate(result.size(), globals)?;
+                    stack.push(result);
+                }
+                Instruction::IsNull => {
+                    let value = stack.pop().unwrap();
+                    stack.push(Value::Boolean(matches!(value, Value::Null)));
+                }
+
+                Instruction::Pop => { stack.pop(); },
+                Instruction::Jump(target) => pc = *target,
+                Instruction::JumpIfFalse(target) => {
+                    if !stack.pop().unwrap().is_truthy() {
+                        pc = *target;
+                    }
+                }
+
+                Instruction::Evaluate(node) => stack.push(self.evaluate(node, globals)?),
+            }
+        }
+
+        Ok(stack.pop().unwrap_or(Value::Null))
+    }
+}
@@ -0,0 +1,61 @@
+use std::{fmt::Debug, sync::{Condvar, Mutex}, thread, time::{Duration, Instant}};
+
+/// Where tasks get the time from, for receive timeouts, the program's deadline, and `sleep`. This
+/// is normally `RealClock`, but can be replaced with a `MockClock` so that time-dependent programs
+/// can be tested without actually waiting.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock which only moves forward when `advance` is called. Sleeping on it waits until it has
+/// been advanced far enough.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    advanced: Condvar,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now(), elapsed: Mutex::new(Duration::ZERO), advanced: Condvar::new() }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+        self.advanced.notify_all();
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let elapsed = self.elapsed.lock().unwrap();
+        let until = *elapsed + duration;
+        drop(self.advanced.wait_while(elapsed, |elapsed| *elapsed < until).unwrap());
+    }
+}
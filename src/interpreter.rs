@@ -1,8 +1,8 @@
-use std::{collections::{HashMap, BTreeMap}, fmt::Display, process::exit};
+use std::{collections::HashMap, fmt::{Debug, Display}, hash::{Hash, Hasher}, process::exit, cmp::{Ordering, Reverse}, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering}}, time::{Duration, Instant}, thread, io::Write};
 
-use crossbeam_channel::{Sender, Receiver, SendError, Select, RecvError};
+use crossbeam_channel::{Sender, Receiver, SendError, Select, RecvError, RecvTimeoutError, TryRecvError, TrySendError};
 
-use crate::node::{Node, NodeKind, BinaryOperator};
+use crate::{barrier::TaskBarrier, clock::Clock, node::{Node, NodeKind, BinaryOperator, UnaryOperator, ValueType}, runtime::{ChannelMetrics, TaskBody, TaskEvent, TaskEventKind, spawn_task}, trace::{Trace, TraceEvent}};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct TaskID(pub usize);
@@ -15,31 +15,99 @@ impl Display for TaskID {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InterpreterError {
+    kind: InterpreterErrorKind,
     message: String,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterpreterErrorKind {
+    General,
+    RecursionLimit,
+    ChannelClosed,
+    TypeMismatch,
+    LivelockSuspected,
+    Cancelled,
+    IntegerOverflow,
+    ReceiveTimeout,
+    DeadlineExceeded,
+    OutputError,
+    MemoryLimitExceeded,
+}
+
 impl InterpreterError {
-    fn new(s: impl Into<String>) -> Self {
-        Self { message: s.into() }
+    pub(crate) fn new(s: impl Into<String>) -> Self {
+        Self::with_kind(InterpreterErrorKind::General, s)
+    }
+
+    pub(crate) fn with_kind(kind: InterpreterErrorKind, s: impl Into<String>) -> Self {
+        Self { kind, message: s.into() }
+    }
+
+    pub fn kind(&self) -> InterpreterErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
     }
 }
 
 impl<T> From<SendError<T>> for InterpreterError {
     fn from(value: SendError<T>) -> Self {
-        InterpreterError::new(format!("send error: {value}"))
+        InterpreterError::with_kind(InterpreterErrorKind::ChannelClosed, format!("send error: {value}"))
     }
 }
 
 impl From<RecvError> for InterpreterError {
     fn from(value: RecvError) -> Self {
-        InterpreterError::new(format!("receive error: {value}"))
+        InterpreterError::with_kind(InterpreterErrorKind::ChannelClosed, format!("receive error: {value}"))
+    }
+}
+
+/// Somewhere for `$out` to write to, shared between all tasks.
+#[derive(Clone)]
+pub struct OutputWriter(pub Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Debug for OutputWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OutputWriter")
     }
 }
 
+pub type SharedChannelMetrics = Arc<Mutex<HashMap<(TaskID, TaskID), ChannelMetrics>>>;
+pub type TaskResult = (TaskID, String, Result<Value, InterpreterError>);
+
 #[derive(Debug, Clone)]
 pub struct Globals {
     pub task_values_by_name: HashMap<String, Value>,
-    pub task_descriptions_by_id: HashMap<TaskID, String>,
+    pub task_descriptions_by_id: Arc<Mutex<HashMap<TaskID, String>>>,
+
+    // Shared so that tasks can spawn more tasks while running
+    pub next_task_id: Arc<AtomicUsize>,
+    pub started_task_count: Arc<AtomicUsize>,
+    pub result_sender: Sender<TaskResult>,
+
+    pub input_receiver: Receiver<Value>,
+    pub warning_sender: Sender<String>,
+    pub busy_wait_warning_threshold: Option<usize>,
+    pub max_evaluation_depth: Option<usize>,
+    pub channel_metrics: Option<SharedChannelMetrics>,
+    pub format_options: FormatOptions,
+    pub output_writer: Option<OutputWriter>,
+    pub trace_recorder: Option<Arc<Mutex<Trace>>>,
+    pub replay: Option<Arc<Trace>>,
+    pub bytecode: bool,
+    pub max_tasks: Option<usize>,
+    pub memory_limit: Option<usize>,
+    pub barriers: HashMap<String, Arc<TaskBarrier>>,
+    pub task_definitions: HashMap<String, (Arc<TaskBody>, usize)>,
+    pub priorities: HashMap<TaskID, i64>,
+    pub message_budget: Option<usize>,
+    pub messages_sent: Arc<AtomicUsize>,
+    pub event_sink: Option<Sender<TaskEvent>>,
+    pub cancelled: Arc<AtomicBool>,
+    pub deadline: Option<Instant>,
+    pub clock: Arc<dyn Clock>,
 }
 
 #[derive(Clone, Debug)]
@@ -47,17 +115,44 @@ pub struct TaskState {
     pub name: String,
     pub id: TaskID,
     pub index: Option<usize>,
+    pub buffer_size: usize,
+    pub linear: bool,
+    pub notify: Vec<String>,
+    pub priority: i64,
 
     pub locals: HashMap<String, Value>,
+    pub depth: usize,
+    pub loop_exit: Option<LoopExit>,
+    pub failed_try_receives: usize,
+    pub allocated: usize,
+    pub replay_position: usize,
+    pub round_robin_cursors: HashMap<Vec<TaskID>, usize>,
+    pub child_results: HashMap<TaskID, Receiver<Result<Value, InterpreterError>>>,
 
     pub receivers: HashMap<TaskID, Receiver<Value>>,
     pub senders: HashMap<TaskID, Sender<Value>>,
 }
 
+/// A `break` or `continue` which is making its way out to the loop it applies to.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoopExit {
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl LoopExit {
+    fn applies_to(&self, loop_label: &Option<String>) -> bool {
+        let (LoopExit::Break(label) | LoopExit::Continue(label)) = self;
+        label.is_none() || label == loop_label
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Value {
     Null,
     Integer(i64),
+    Float(f64),
+    String(String),
     Boolean(bool),
     TaskReference(TaskID, String),
     MagicTaskReference(MagicTask),
@@ -65,68 +160,420 @@ pub enum Value {
     Range {
         begin: Box<Value>,
         end: Box<Value>,
-    }
+    },
+    Tagged {
+        tag: String,
+        fields: Vec<Value>,
+    },
+    /// Pairs of keys and values, in the order they were inserted. Each key appears at most once.
+    Map(Vec<(Value, Value)>),
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Controls how values are converted to strings, such as when they're sent to `$out`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The number of digits shown after the decimal point of floats. If not given, floats use as
+    /// many digits as they need to be read back exactly.
+    pub float_precision: Option<usize>,
+    pub float_notation: FloatNotation,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FloatNotation {
+    /// Scientific notation for very large or small floats, and decimal notation otherwise.
+    #[default]
+    Auto,
+    Decimal,
+    Scientific,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MagicTask {
     Out,
+    In,
 }
 
-impl Value {
-    fn is_truthy(&self) -> bool {
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            // Task references are equal if they refer to the same task - the name is only for
+            // display purposes
+            (Self::TaskReference(l, _), Self::TaskReference(r, _)) => l == r,
+
+            (Self::Null, Self::Null) => true,
+            (Self::Integer(l), Self::Integer(r)) => l == r,
+            (Self::Float(l), Self::Float(r)) => l == r,
+            (Self::String(l), Self::String(r)) => l == r,
+            (Self::Boolean(l), Self::Boolean(r)) => l == r,
+            (Self::MagicTaskReference(l), Self::MagicTaskReference(r)) => l == r,
+            (Self::Array(l), Self::Array(r)) => l == r,
+            (Self::Range { begin: lb, end: le }, Self::Range { begin: rb, end: re })
+                => lb == rb && le == re,
+            (Self::Tagged { tag: lt, fields: lf }, Self::Tagged { tag: rt, fields: rf })
+                => lt == rt && lf == rf,
+
+            // Maps are equal regardless of the order their keys were inserted in
+            (Self::Map(l), Self::Map(r))
+                => l.len() == r.len() && l.iter().all(|(k, v)| Self::map_get(r, k) == Some(v)),
+
+            _ => false,
+        }
+    }
+}
+
+/// The integer bounds of a range, either of which may be missing. Ranges exclude their end, and
+/// count down if the end is before the beginning.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct IntegerRange {
+    pub begin: Option<i64>,
+    pub end: Option<i64>,
+}
+
+impl IntegerRange {
+    /// Every integer in the range in order, or `None` if there's no beginning to start from.
+    pub(crate) fn iter(&self) -> Option<Box<dyn Iterator<Item = i64>>> {
+        let begin = self.begin?;
+        Some(match self.end {
+            None => Box::new(begin..),
+            Some(end) if begin > end => Box::new((end + 1..=begin).rev()),
+            Some(end) => Box::new(begin..end),
+        })
+    }
+
+    pub(crate) fn contains(&self, item: i64) -> bool {
+        match (self.begin, self.end) {
+            (Some(begin), Some(end)) if begin > end => end < item && item <= begin,
+            (begin, end) => begin.is_none_or(|b| b <= item) && end.is_none_or(|e| item < e),
+        }
+    }
+
+    /// How many integers are in the range, or `None` if it's missing a bound.
+    pub(crate) fn len(&self) -> Option<usize> {
+        Some(self.begin?.abs_diff(self.end?) as usize)
+    }
+
+    /// The integer `offset` steps along from the beginning. Only meaningful if `len` is `Some`.
+    pub(crate) fn nth(&self, offset: usize) -> i64 {
+        let (begin, end) = (self.begin.unwrap_or(0), self.end.unwrap_or(0));
+        let step = if begin <= end { 1 } else { -1 };
+        begin + offset as i64 * step
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
         match self {
-            Self::Boolean(false) => false,
-            Self::Null => false,
+            Self::TaskReference(id, _) => id.hash(state),
+            Self::Integer(i) => i.hash(state),
+            Self::String(s) => s.hash(state),
+            Self::Boolean(b) => b.hash(state),
+            Self::MagicTaskReference(m) => m.hash(state),
+            Self::Array(items) => items.hash(state),
+            Self::Range { begin, end } => (begin, end).hash(state),
+            Self::Tagged { tag, fields } => (tag, fields).hash(state),
+
+            // Equal floats can have different bits (like 0.0 and -0.0), and equal maps can have
+            // their keys in a different order, so only the length goes into the hash for these
+            Self::Map(pairs) => pairs.len().hash(state),
+            Self::Null | Self::Float(_) => (),
+        }
+    }
+}
+
+impl Value {
+    pub(crate) fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Boolean(false) | Self::Null)
+    }
+
+    pub fn from_literal(node: &Node) -> Option<Value> {
+        match &node.kind {
+            NodeKind::IntegerLiteral(i) => Some(Value::Integer(*i)),
+            NodeKind::FloatLiteral(f) => Some(Value::Float(*f)),
+            NodeKind::StringLiteral(s) => Some(Value::String(s.clone())),
+            NodeKind::BooleanLiteral(b) => Some(Value::Boolean(*b)),
+            NodeKind::NullLiteral => Some(Value::Null),
+            NodeKind::ArrayLiteral(items) => items.iter()
+                .map(Value::from_literal)
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array),
+            NodeKind::Tagged { tag, fields } => fields.iter()
+                .map(Value::from_literal)
+                .collect::<Option<Vec<_>>>()
+                .map(|fields| Value::Tagged { tag: tag.clone(), fields }),
 
-            _ => true,
+            _ => None,
         }
     }
 
-    fn get_integer(&self) -> Result<i64, InterpreterError> {
+    /// How many elements this value holds, counting the items of arrays (and of any arrays
+    /// nested in them), the entries of maps, and the bytes of strings.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::String(s) => s.len(),
+            Value::Array(items) | Value::Tagged { fields: items, .. } =>
+                items.len() + items.iter().map(Value::size).sum::<usize>(),
+            Value::Map(pairs) =>
+                pairs.len() + pairs.iter().map(|(k, v)| k.size() + v.size()).sum::<usize>(),
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn get_integer(&self) -> Result<i64, InterpreterError> {
         match self {
             Value::Integer(i) => Ok(*i),
             _ => Err(InterpreterError::new("expected an integer"))
         }
     }
 
-    fn get_task_id<'a>(&'a self) -> Result<TaskID, InterpreterError> {
+    pub(crate) fn integer_range(&self) -> Result<IntegerRange, InterpreterError> {
+        let Value::Range { begin, end } = self else {
+            return Err(InterpreterError::new("expected a range"))
+        };
+        let bound = |bound: &Value| match bound {
+            Value::Null => Ok(None),
+            _ => bound.get_integer().map(Some),
+        };
+        Ok(IntegerRange { begin: bound(begin)?, end: bound(end)? })
+    }
+
+    pub(crate) fn get_float(&self) -> Result<f64, InterpreterError> {
+        match self {
+            Value::Integer(i) => Ok(*i as f64),
+            Value::Float(f) => Ok(*f),
+            _ => Err(InterpreterError::new("expected a number"))
+        }
+    }
+
+    pub(crate) fn get_array(&self) -> Result<&Vec<Value>, InterpreterError> {
+        match self {
+            Value::Array(items) => Ok(items),
+            _ => Err(InterpreterError::new("expected an array"))
+        }
+    }
+
+    pub(crate) fn compare(&self, other: &Value) -> Result<Ordering, InterpreterError> {
+        match (self, other) {
+            (Value::Integer(l), Value::Integer(r)) => Ok(l.cmp(r)),
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) =>
+                self.get_float()?.partial_cmp(&other.get_float()?)
+                    .ok_or_else(|| InterpreterError::new("cannot compare NaN")),
+            (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+            (Value::Boolean(l), Value::Boolean(r)) => Ok(l.cmp(r)),
+
+            // Arrays are compared lexicographically
+            (Value::Array(l), Value::Array(r)) => {
+                for (l, r) in l.iter().zip(r) {
+                    let ordering = l.compare(r)?;
+                    if ordering != Ordering::Equal {
+                        return Ok(ordering)
+                    }
+                }
+                Ok(l.len().cmp(&r.len()))
+            }
+
+            _ => Err(InterpreterError::new(format!("cannot compare {} with {}",
+                self.to_printable_string(), other.to_printable_string()))),
+        }
+    }
+
+    pub fn has_type(&self, ty: ValueType) -> bool {
+        match ty {
+            ValueType::Integer => matches!(self, Value::Integer(_)),
+            ValueType::Float => matches!(self, Value::Float(_)),
+            ValueType::Boolean => matches!(self, Value::Boolean(_)),
+            ValueType::String => matches!(self, Value::String(_)),
+            ValueType::Array => matches!(self, Value::Array(_)),
+            ValueType::Task => matches!(self, Value::TaskReference(..) | Value::MagicTaskReference(_)),
+            ValueType::Null => matches!(self, Value::Null),
+            ValueType::Map => matches!(self, Value::Map(_)),
+        }
+    }
+
+    /// Whether this can be used as a map key - floats can't, since NaN isn't equal to itself.
+    pub(crate) fn is_hashable(&self) -> bool {
         match self {
-            Value::TaskReference(id, _) => Ok(id.clone()),
+            Value::Null | Value::Integer(_) | Value::String(_) | Value::Boolean(_)
+            | Value::TaskReference(..) | Value::MagicTaskReference(_) => true,
+            Value::Array(items) | Value::Tagged { fields: items, .. } => items.iter().all(Value::is_hashable),
+            Value::Float(_) | Value::Range { .. } | Value::Map(_) => false,
+        }
+    }
+
+    pub(crate) fn map_get<'a>(pairs: &'a [(Value, Value)], key: &Value) -> Option<&'a Value> {
+        pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub(crate) fn get_task_id(&self) -> Result<TaskID, InterpreterError> {
+        match self {
+            Value::TaskReference(id, _) => Ok(*id),
             _ => Err(InterpreterError::new("expected a task")),
         }
     }
 
-    fn to_printable_string(&self) -> String {
+    pub fn to_printable_string(&self) -> String {
+        self.to_formatted_string(&FormatOptions::default())
+    }
+
+    pub fn to_formatted_string(&self, options: &FormatOptions) -> String {
         match self {
             Value::Null => "null".to_string(),
             Value::Integer(i) => i.to_string(),
+            Value::Float(f) => Self::format_float(*f, options),
+            Value::String(s) => s.clone(),
             Value::Boolean(b) => b.to_string(),
             Value::TaskReference(_, name) => format!("<task {name}>"),
             Value::MagicTaskReference(ty) => format!("<task (magic) {}>", match ty {
                 MagicTask::Out => "$out",
+                MagicTask::In => "$in",
             }),
             Value::Array(vals) => format!("[ {} ]",
-                vals.iter().map(|v| v.to_printable_string()).collect::<Vec<_>>().join(", ")),
-            Value::Range { begin, end } => format!("{} .. {}",
-                begin.to_printable_string(), end.to_printable_string()),
+                vals.iter().map(|v| v.to_formatted_string(options)).collect::<Vec<_>>().join(", ")),
+            Value::Range { begin, end } => {
+                let format_bound = |bound: &Value| match bound {
+                    Value::Null => String::new(),
+                    _ => bound.to_formatted_string(options),
+                };
+                format!("{} .. {}", format_bound(begin), format_bound(end)).trim().to_string()
+            }
+            Value::Tagged { tag, fields } => format!("{tag}({})",
+                fields.iter().map(|v| v.to_formatted_string(options)).collect::<Vec<_>>().join(", ")),
+            Value::Map(pairs) => format!("{{ {} }}",
+                pairs.iter().map(|(k, v)| format!("{}: {}", k.to_formatted_string(options), v.to_formatted_string(options))).collect::<Vec<_>>().join(", ")),
+        }
+    }
+
+    fn format_float(f: f64, options: &FormatOptions) -> String {
+        let notation = match options.float_notation {
+            // Very large or small numbers would have a lot of zeroes
+            FloatNotation::Auto if f.is_finite() && f != 0.0 && (f.abs() >= 1e16 || f.abs() < 1e-4)
+                => FloatNotation::Scientific,
+            FloatNotation::Auto => FloatNotation::Decimal,
+            notation => notation,
+        };
+
+        let formatted = match (notation, options.float_precision) {
+            (FloatNotation::Scientific, Some(precision)) => format!("{f:.precision$e}"),
+            (FloatNotation::Scientific, None) => format!("{f:e}"),
+            (_, Some(precision)) => format!("{f:.precision$}"),
+            (_, None) => format!("{f}"),
+        };
+
+        // Make sure floats can't be confused with integers
+        if f.is_finite() && !formatted.contains(['.', 'e']) {
+            format!("{formatted}.0")
+        } else {
+            formatted
+        }
+    }
+}
+
+impl Globals {
+    pub fn task_description(&self, id: &TaskID) -> String {
+        self.task_descriptions_by_id.lock().unwrap()[id].clone()
+    }
+
+    pub fn take_task_id(&self) -> Result<TaskID, InterpreterError> {
+        self.check_task_limit(1)?;
+        Ok(TaskID(self.next_task_id.fetch_add(1, AtomicOrdering::SeqCst)))
+    }
+
+    /// Checks that creating this many more tasks wouldn't exceed the limit on the number of tasks.
+    pub fn check_task_limit(&self, new_tasks: usize) -> Result<(), InterpreterError> {
+        let Some(max_tasks) = self.max_tasks else {
+            return Ok(())
+        };
+
+        // IDs start from 1, and every task has one
+        let existing_tasks = self.next_task_id.load(AtomicOrdering::SeqCst) - 1;
+        if existing_tasks + new_tasks > max_tasks {
+            Err(InterpreterError::new(format!("cannot create more than {max_tasks} tasks")))
+        } else {
+            Ok(())
         }
     }
 }
 
 impl TaskState {
+    pub fn new(name: &str, id: TaskID, index: Option<usize>, buffer_size: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            id,
+            index,
+            buffer_size,
+            linear: false,
+            notify: vec![],
+            priority: 0,
+
+            locals: HashMap::new(),
+            depth: 0,
+            loop_exit: None,
+            failed_try_receives: 0,
+            allocated: 0,
+            replay_position: 0,
+            round_robin_cursors: HashMap::new(),
+            child_results: HashMap::new(),
+
+            receivers: HashMap::new(),
+            senders: HashMap::new(),
+        }
+    }
+
+    /// The task's locals, sorted by name so that they can be displayed in a stable order.
+    pub fn locals_snapshot(&self) -> Vec<(String, Value)> {
+        let mut locals: Vec<_> = self.locals.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        locals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        locals
+    }
+
     pub fn evaluate(&mut self, node: &Node, globals: &Globals) -> Result<Value, InterpreterError> {
+        Self::check_cancelled(globals)?;
+
+        // Deeply nested code would otherwise overflow the thread's stack and crash everything
+        if let Some(max_depth) = globals.max_evaluation_depth {
+            if self.depth >= max_depth {
+                return Err(InterpreterError::with_kind(InterpreterErrorKind::RecursionLimit,
+                    format!("exceeded maximum evaluation depth of {max_depth}")))
+            }
+        }
+
+        self.depth += 1;
+        let result = self.evaluate_node(node, globals);
+        self.depth -= 1;
+
+        // Only nodes which can build a bigger value than they were given need checking
+        if let Ok(value) = &result {
+            if globals.memory_limit.is_some() && matches!(node.kind,
+                NodeKind::ArrayLiteral(_) | NodeKind::Tagged { .. } | NodeKind::Range { .. } | NodeKind::Call { .. } | NodeKind::BinaryOperation { .. }) {
+                self.allocate(value.size(), globals)?;
+            }
+        }
+        result
+    }
+
+    fn evaluate_node(&mut self, node: &Node, globals: &Globals) -> Result<Value, InterpreterError> {
         match &node.kind {
             NodeKind::Body(v) => {
                 let mut result = Value::Null;
                 for i in v {
                     result = self.evaluate(i, globals)?;
+
+                    // Stop early if we're breaking out of a loop
+                    if self.loop_exit.is_some() {
+                        break
+                    }
                 }
                 Ok(result)
             }
 
             NodeKind::IntegerLiteral(i)
                 => Ok(Value::Integer(*i)),
+            NodeKind::FloatLiteral(f)
+                => Ok(Value::Float(*f)),
+            NodeKind::StringLiteral(s)
+                => Ok(Value::String(s.clone())),
             NodeKind::BooleanLiteral(b)
                 => Ok(Value::Boolean(*b)),
             NodeKind::NullLiteral
@@ -135,73 +582,225 @@ impl TaskState {
                 => Ok(Value::Array(items.iter()
                     .map(|i| self.evaluate(i, globals))
                     .collect::<Result<Vec<_>, _>>()?)),
+            NodeKind::Tagged { tag, fields }
+                => Ok(Value::Tagged {
+                    tag: tag.clone(),
+                    fields: fields.iter()
+                        .map(|f| self.evaluate(f, globals))
+                        .collect::<Result<Vec<_>, _>>()?,
+                }),
 
             NodeKind::Range { begin, end } => {
-                let begin = self.evaluate(begin, globals)?;
-                let end = self.evaluate(end, globals)?;
+                // Missing bounds are represented by `null`
+                let begin = match begin {
+                    Some(begin) => self.evaluate(begin, globals)?,
+                    None => Value::Null,
+                };
+                let end = match end {
+                    Some(end) => self.evaluate(end, globals)?,
+                    None => Value::Null,
+                };
 
                 Ok(Value::Range { begin: Box::new(begin), end: Box::new(end) })
             },
 
             NodeKind::Identifier(name)
-                => self.resolve(&name, globals),
+                => self.resolve(name, globals),
+            NodeKind::Call { name, arguments }
+                => self.call_builtin(name, arguments, globals),
+            NodeKind::Lambda { .. }
+                => Err(InterpreterError::new("lambdas can only be passed to builtins")),
+            NodeKind::AnonymousTask(body)
+                => self.spawn_anonymous_task(body, globals),
+            NodeKind::Debug { value, source } => {
+                let value = self.evaluate(value, globals)?;
+                eprintln!("{source} = {}", value.to_formatted_string(&globals.format_options));
+                Ok(value)
+            }
+            NodeKind::Spawn { count, task } => {
+                let count = self.evaluate(count, globals)?.get_integer()?;
+                self.spawn_instances(task, count, globals)
+            }
             
+            // The right side is only evaluated if it's needed, since it could send or receive
+            NodeKind::BinaryOperation { left, op: op @ (BinaryOperator::And | BinaryOperator::Or), right } => {
+                let left = self.evaluate(left, globals)?.is_truthy();
+                if left == (*op == BinaryOperator::Or) {
+                    return Ok(Value::Boolean(left))
+                }
+                Ok(Value::Boolean(self.evaluate(right, globals)?.is_truthy()))
+            }
+
             NodeKind::BinaryOperation { left, op, right } => {
-                let left = self.evaluate(&left, globals)?.get_integer()?;
-                let right = self.evaluate(&right, globals)?.get_integer()?;
+                let left = self.evaluate(left, globals)?;
+                let right = self.evaluate(right, globals)?;
+                Self::binary_operation(*op, left, right)
+            }
+
+            NodeKind::UnaryOperation { op, value } => {
+                let value = self.evaluate(value, globals)?;
 
                 Ok(match op {
-                    BinaryOperator::Add         => Value::Integer(left + right),
-                    BinaryOperator::Subtract    => Value::Integer(left - right),
-                    BinaryOperator::Multiply    => Value::Integer(left * right),
-                    BinaryOperator::Divide      => Value::Integer(left / right),
-
-                    BinaryOperator::Equals      => Value::Boolean(left == right),
-                    BinaryOperator::LessThan    => Value::Boolean(left < right),
-                    BinaryOperator::GreaterThan => Value::Boolean(left > right),
+                    UnaryOperator::IsNull => Value::Boolean(matches!(value, Value::Null)),
+                    UnaryOperator::Not => Value::Boolean(!value.is_truthy()),
+                    UnaryOperator::Ready => {
+                        let ready = match self.next_replay_event(globals)? {
+                            Some(TraceEvent::Ready(ready)) => ready,
+                            Some(_) => return Err(Self::replay_mismatch()),
+                            None => self.is_ready(&value, globals)?,
+                        };
+                        self.record_trace_event(globals, TraceEvent::Ready(ready));
+                        Value::Boolean(ready)
+                    }
+                    UnaryOperator::Await => self.await_task(&value)?,
+                    UnaryOperator::Negate => match value {
+                        Value::Float(f) => Value::Float(-f),
+                        Value::Integer(i) => Value::Integer(i.checked_neg()
+                            .ok_or_else(|| InterpreterError::with_kind(InterpreterErrorKind::IntegerOverflow, format!("-({i}) overflows")))?),
+                        _ => return Err(InterpreterError::new(format!("cannot negate {}", value.to_printable_string()))),
+                    },
+                    UnaryOperator::Pending => {
+                        let pending = match self.next_replay_event(globals)? {
+                            Some(TraceEvent::Pending(pending)) => pending,
+                            Some(_) => return Err(Self::replay_mismatch()),
+                            None => self.pending_count(&value, globals)?,
+                        };
+                        self.record_trace_event(globals, TraceEvent::Pending(pending));
+                        Value::Integer(pending as i64)
+                    }
                 })
             }
 
-            NodeKind::If { condition, if_true } => {
-                let condition = self.evaluate(&condition, globals)?;
+            NodeKind::If { condition, if_true, if_false } => {
+                let condition = self.evaluate(condition, globals)?;
 
                 if condition.is_truthy() {
-                    self.evaluate(&if_true, globals)
+                    self.evaluate(if_true, globals)
+                } else if let Some(if_false) = if_false {
+                    self.evaluate(if_false, globals)
                 } else {
                     Ok(Value::Null)
                 }
             }
 
-            NodeKind::While { condition, body } => {
+            NodeKind::While { condition, body, label } => {
                 let mut result = Value::Null;
                 loop {
-                    let cond = self.evaluate(&condition, globals)?;
+                    let cond = self.evaluate(condition, globals)?;
                     if !cond.is_truthy() {
                         break
                     }
 
-                    result = self.evaluate(&body, globals)?
+                    result = self.evaluate(body, globals)?;
+                    if self.finish_iteration(label) {
+                        break
+                    }
                 }
                 Ok(result)
             }
 
-            NodeKind::Assign { value, destination } => {
-                let value = self.evaluate(&value, globals)?;
+            NodeKind::For { binding, iterable, body, label } => {
+                let items: Box<dyn Iterator<Item = Value>> = match self.evaluate(iterable, globals)? {
+                    Value::Array(items) => Box::new(items.into_iter()),
+                    range @ Value::Range { .. } => {
+                        let Some(items) = range.integer_range()?.iter() else {
+                            return Err(InterpreterError::new("`for` can only iterate over a range with a beginning"))
+                        };
+                        Box::new(items.map(Value::Integer))
+                    }
 
-                // Assign to local
-                let NodeKind::Identifier(dest_local) = &destination.kind else {
-                    return Err(InterpreterError::new("expected identifier for result of assign"))
+                    other => return Err(InterpreterError::with_kind(InterpreterErrorKind::TypeMismatch,
+                        format!("`for` expected an array or range, but found {}", other.to_printable_string()))),
+                };
+
+                let mut result = Value::Null;
+                for item in items {
+                    self.assign_local(binding, item, globals);
+                    result = self.evaluate(body, globals)?;
+                    if self.finish_iteration(label) {
+                        break
+                    }
+                }
+                Ok(result)
+            }
+
+            NodeKind::Break(label) => {
+                self.loop_exit = Some(LoopExit::Break(label.clone()));
+                Ok(Value::Null)
+            }
+            NodeKind::Continue(label) => {
+                self.loop_exit = Some(LoopExit::Continue(label.clone()));
+                Ok(Value::Null)
+            }
+
+            NodeKind::Try { body, handler, binding } => {
+                // Only errors caused by the task itself are caught - if a channel has closed or
+                // the task has recursed too deeply, there's nothing it can do to recover
+                match self.evaluate(body, globals) {
+                    Err(e) if matches!(e.kind, InterpreterErrorKind::General | InterpreterErrorKind::TypeMismatch | InterpreterErrorKind::IntegerOverflow | InterpreterErrorKind::ReceiveTimeout | InterpreterErrorKind::OutputError) => {
+                        if let Some(binding) = binding {
+                            self.locals.insert(binding.clone(), Value::String(e.message));
+                        }
+                        self.evaluate(handler, globals)
+                    }
+                    result => result,
+                }
+            }
+
+            NodeKind::Barrier => {
+                // Wait for every instance of this multi-task to get here - other tasks don't have
+                // anyone to wait for
+                if let Some(barrier) = globals.barriers.get(&self.name) {
+                    barrier.wait(globals)?;
+                }
+                Ok(Value::Null)
+            }
+
+            NodeKind::Yield => {
+                // Tasks are threads, so this is only a hint to the OS scheduler
+                thread::yield_now();
+                Ok(Value::Null)
+            }
+
+            NodeKind::Noop => Ok(Value::Null),
+
+            NodeKind::Flush(channel) => {
+                let channel = self.evaluate(channel, globals)?;
+                if channel != Value::MagicTaskReference(MagicTask::Out) {
+                    return Err(InterpreterError::new(format!("only `$out` can be flushed, not {}", channel.to_printable_string())))
+                }
+
+                let result = match &globals.output_writer {
+                    Some(OutputWriter(writer)) => writer.lock().unwrap().flush(),
+                    None => std::io::stdout().flush(),
                 };
-                self.create_or_assign_local(&dest_local, value);
+                result.map_err(|e| InterpreterError::with_kind(InterpreterErrorKind::OutputError, format!("failed to flush output: {e}")))?;
+                Ok(Value::Null)
+            }
+
+            NodeKind::Assign { value, destination } => {
+                let value = self.evaluate(value, globals)?;
+                self.destructure(destination, value, globals)?;
 
                 Ok(Value::Null)
             }
 
             NodeKind::Index { value, index } => {
-                let value = self.evaluate(&value, globals)?;
-                let index = self.evaluate(&index, globals)?;
+                let value = self.evaluate(value, globals)?;
+                let index = self.evaluate(index, globals)?;
+
+                // Ranges can be indexed as if they were arrays, without creating one
+                if let Value::Range { .. } = value {
+                    return Self::index_range(value.integer_range()?, index)
+                }
+
+                if let Value::Map(pairs) = &value {
+                    return Value::map_get(pairs, &index).cloned()
+                        .ok_or_else(|| InterpreterError::new(format!("key {} is not in the map", index.to_printable_string())))
+                }
 
-                let Value::Array(ref items) = value else {
+                // A tagged value's fields can be indexed like an array
+                let (Value::Array(ref items) | Value::Tagged { fields: ref items, .. }) = value else {
                     return Err(InterpreterError::new("expected array"))
                 };
 
@@ -215,8 +814,7 @@ impl TaskState {
                     },
 
                     Value::Range { begin, end } => {
-                        let begin_val = Self::wrap_as_index(begin.get_integer()?, items.len());
-                        let end_val = Self::wrap_as_index(end.get_integer()?, items.len());
+                        let (begin_val, end_val) = Self::slice_bounds(&begin, &end, items.len())?;
 
                         if let Some(items) = items.get(begin_val..end_val) {
                             Ok(Value::Array(items.to_vec()))
@@ -226,91 +824,195 @@ impl TaskState {
                         }
                     }
 
-                    _ => Err(InterpreterError::new(format!("expected integer or range as index")))
+                    _ => Err(InterpreterError::new("expected integer or range as index".to_string()))
                 }
             }
             
-            NodeKind::Send { value, channel } => {
-                let value = self.evaluate(&value, globals)?;
+            NodeKind::Send { value, channel, try_send } => {
+                let value = self.evaluate(value, globals)?;
 
                 // Resolve the channel
-                let channel = self.evaluate(&channel, globals)?;
+                let channel = self.evaluate(channel, globals)?;
                 if let Value::MagicTaskReference(magic) = channel {
                     match magic {
-                        MagicTask::Out => println!("{}", value.to_printable_string()),
+                        MagicTask::Out => {
+                            let line = value.to_formatted_string(&globals.format_options);
+                            let result = match &globals.output_writer {
+                                Some(OutputWriter(writer)) => writeln!(writer.lock().unwrap(), "{line}"),
+                                None => writeln!(std::io::stdout(), "{line}"),
+                            };
+                            result.map_err(|e| InterpreterError::with_kind(InterpreterErrorKind::OutputError, format!("failed to write output: {e}")))?;
+                        }
+                        MagicTask::In => return Err(InterpreterError::new("cannot send to `$in`")),
                     }
-                    return Ok(Value::Null)
+                    return Ok(if *try_send { Value::Boolean(true) } else { Value::Null })
                 }
 
                 // We'll assume it's a normal task - get its sender
                 let other_task_id = channel.get_task_id()?;
+                Self::count_message(globals)?;
+                let replay_event = if *try_send { self.next_replay_event(globals)? } else { None };
                 let task_sender = self.get_sender_to_task(&other_task_id)?;
 
-                // Actually perform send
-                task_sender.send(value)?;
+                // A non-blocking send only succeeds if there's buffer space, or the receiver is
+                // already waiting
+                if *try_send {
+                    let sent = match replay_event {
+                        // Blocking is fine, since the receiver will be replaying its receive too
+                        Some(TraceEvent::TrySent(true)) => {
+                            task_sender.send(value)?;
+                            true
+                        }
+                        Some(TraceEvent::TrySent(false)) => false,
+                        Some(_) => return Err(Self::replay_mismatch()),
+
+                        None => match task_sender.try_send(value) {
+                            Ok(()) => true,
+                            Err(TrySendError::Full(_)) => false,
+                            Err(TrySendError::Disconnected(value)) => return Err(SendError(value).into()),
+                        }
+                    };
+                    self.record_trace_event(globals, TraceEvent::TrySent(sent));
+                    return Ok(Value::Boolean(sent))
+                }
+
+                // Actually perform send, timing how long it blocks for if we're measuring that
+                if let Some(metrics) = &globals.channel_metrics {
+                    let start = Instant::now();
+                    task_sender.send(value)?;
+                    let blocked = start.elapsed();
+
+                    let mut metrics = metrics.lock().unwrap();
+                    let channel_metrics = metrics.entry((self.id, other_task_id)).or_default();
+                    channel_metrics.sends += 1;
+                    channel_metrics.total_blocked += blocked;
+                    channel_metrics.max_blocked = channel_metrics.max_blocked.max(blocked);
+                } else {
+                    task_sender.send(value)?;
+                }
 
                 Ok(Value::Null)
             },
 
-            NodeKind::Receive { value, channel, bind_channel } => {
-                if *bind_channel {
-                    // Receive from anything using select
-                    let ids_and_receivers: Vec<_> = self.receivers.iter().collect();
-                    let mut selector = Select::new();
-                    for (_, chan) in &ids_and_receivers {
-                        selector.recv(chan);
+            NodeKind::Receive { value, channel, bind_channel, default, try_receive, expected_type, timeout } => {
+                // A receive's own timeout can't extend past the program's deadline, so wait for
+                // whichever is sooner
+                let timeout = match timeout {
+                    Some(timeout) => match self.evaluate(timeout, globals)? {
+                        Value::Integer(ms) if ms >= 0 => Some(Duration::from_millis(ms as u64)),
+                        v => return Err(InterpreterError::new(format!("receive timeout must be a non-negative integer of milliseconds, got {}", v.to_printable_string()))),
+                    },
+                    None => None,
+                };
+                let deadline = [timeout.map(|t| globals.clock.now() + t), globals.deadline].into_iter().flatten().min();
+
+                // Perform the receive, getting the task which was received from
+                // (The task is `None` if the channel isn't associated with a task, like `$in`)
+                let received = match self.next_replay_event(globals)? {
+                    Some(event) => self.replay_receive(event, globals)?,
+                    None => self.receive(channel, *bind_channel, !*try_receive, deadline, globals)?,
+                };
+
+                // Other tasks stopping at the deadline can close the channel first, but the
+                // deadline is the real reason this failed
+                if received.is_err() {
+                    Self::check_cancelled(globals)?;
+                }
+                self.record_trace_event(globals, match &received {
+                    Ok((id, _)) => TraceEvent::Received(*id),
+                    Err(TryRecvError::Empty) => TraceEvent::Empty,
+                    Err(TryRecvError::Disconnected) => TraceEvent::Closed,
+                });
+
+                // Check the value is what the receiver expects - a default isn't checked, since it
+                // didn't come from another task
+                if let (Ok((_, received_value)), Some(expected_type)) = (&received, expected_type) {
+                    if !received_value.has_type(*expected_type) {
+                        return Err(InterpreterError::with_kind(InterpreterErrorKind::TypeMismatch,
+                            format!("expected to receive a value of type {}, but received {}",
+                                expected_type, received_value.to_printable_string())))
                     }
-                    let selected = selector.select();
-                    
-                    // Figure out which channel we received from
-                    let (received_from, received_on_chan) = ids_and_receivers[selected.index()];
-                    let received_from_name = globals.task_descriptions_by_id.get(received_from).unwrap().clone();
-
-                    // Fetch sent value and result variable
-                    let received_value = selected.recv(received_on_chan)?;
-                    let NodeKind::Identifier(value_local) = &value.kind else {
-                        return Err(InterpreterError::new("expected identifier for result of assign"))
-                    };
+                }
 
-                    // Get channel variable
-                    let NodeKind::Identifier(receiver_local) = &channel.kind else {
-                        return Err(InterpreterError::new("expected identifier to assign to as binding channel receiver"))
-                    };
+                // If the channel is closed, use the default if there is one
+                let (received_from, received_value) = match received {
+                    Ok((Some(id), value)) => {
+                        let name = globals.task_description(&id);
+                        (Value::TaskReference(id, name), value)
+                    }
+                    Ok((None, value)) => (Value::Null, value),
 
-                    // Assign value and channel
-                    self.create_or_assign_local(&receiver_local, Value::TaskReference(received_from.clone(), received_from_name));
-                    self.create_or_assign_local(&value_local, received_value);
+                    // A blocking receive only comes back empty if it ran out of time
+                    Err(TryRecvError::Empty) if !*try_receive => {
+                        return Err(InterpreterError::with_kind(InterpreterErrorKind::ReceiveTimeout,
+                            format!("receive timed out after {}ms", timeout.unwrap_or_default().as_millis())))
+                    }
+                    Err(TryRecvError::Empty) => {
+                        self.record_failed_try_receive(globals);
+                        return Ok(Value::Boolean(false))
+                    }
 
-                    Ok(Value::Null)
-                } else {
-                    // Look up channel to receive on
-                    let receiving_from_val = self.evaluate(&channel, globals)?;
-                    let Value::TaskReference(id, _) = receiving_from_val else {
-                        return Err(InterpreterError::new("tried to receive from non-channel"))
-                    };
+                    Err(TryRecvError::Disconnected) => match default {
+                        Some(default) => (Value::Null, self.evaluate(default, globals)?),
+                        None if *bind_channel => return Err(InterpreterError::with_kind(InterpreterErrorKind::ChannelClosed, "all channels are closed")),
+                        None => return Err(RecvError.into()),
+                    }
+                };
+                self.failed_try_receives = 0;
 
-                    // Get receiver
-                    let receiver = self.get_receiver_from_task(&id)?;
+                // Assign into result variable
+                let NodeKind::Identifier(value_local) = &value.kind else {
+                    return Err(InterpreterError::new("expected identifier for result of assign"))
+                };
+                self.assign_local(value_local, received_value, globals);
 
-                    // Fetch sent value and assign into result variable
-                    let received_value = receiver.recv()?;
-                    let NodeKind::Identifier(value_local) = &value.kind else {
-                        return Err(InterpreterError::new("expected identifier for result of assign"))
+                // Binding receives also assign the channel
+                if *bind_channel {
+                    let NodeKind::Identifier(receiver_local) = &channel.kind else {
+                        return Err(InterpreterError::new("expected identifier to assign to as binding channel receiver"))
                     };
-                    self.create_or_assign_local(&value_local, received_value);
+                    self.assign_local(receiver_local, received_from, globals);
+                }
 
+                if *try_receive {
+                    Ok(Value::Boolean(true))
+                } else {
                     Ok(Value::Null)
                 }
             }
 
-            NodeKind::Exit => exit(0),
+            NodeKind::Exit(value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value, globals)?,
+                    None => Value::Null,
+                };
+                // Small integers become the process' exit code, and anything else which was given
+                // is a failure
+                match value {
+                    Value::Null => exit(0),
+                    Value::Integer(code @ 0..=255) => exit(code as i32),
+                    _ => exit(1),
+                }
+            }
         }
     }
 
-    fn resolve(&self, name: &str, globals: &Globals) -> Result<Value, InterpreterError> {
+    pub(crate) fn resolve(&self, name: &str, globals: &Globals) -> Result<Value, InterpreterError> {
         // Check magic stuff
         match name {
             "$out" => return Ok(Value::MagicTaskReference(MagicTask::Out)),
+            "$in" => return Ok(Value::MagicTaskReference(MagicTask::In)),
+            "$arg" => return Ok(self.locals.get("$arg").cloned().unwrap_or(Value::Null)),
+            "$peers" => {
+                // Every task this one has a channel with, in a stable order
+                let mut ids: Vec<_> = self.senders.keys().chain(self.receivers.keys()).copied().collect();
+                ids.sort_by_key(|id| id.0);
+                ids.dedup();
+
+                return Ok(Value::Array(ids.into_iter()
+                    .map(|id| Value::TaskReference(id, globals.task_description(&id)))
+                    .collect()))
+            }
             "$index" => 
                 if let Some(index) = self.index {
                     return Ok(Value::Integer(index as i64))
@@ -334,7 +1036,618 @@ impl TaskState {
         Err(InterpreterError::new(format!("could not find `{name}`")))
     }
 
-    fn create_or_assign_local(&mut self, name: &str, value: Value) {
+    fn is_ready(&self, channel: &Value, globals: &Globals) -> Result<bool, InterpreterError> {
+        // Unbuffered channels are always empty, since values are handed over directly
+        match channel {
+            Value::TaskReference(id, _) => Ok(!self.get_receiver_from_task(id)?.is_empty()),
+            Value::MagicTaskReference(MagicTask::In) => Ok(!globals.input_receiver.is_empty()),
+            Value::Array(items) => {
+                for item in items {
+                    if self.is_ready(item, globals)? {
+                        return Ok(true)
+                    }
+                }
+                Ok(false)
+            }
+
+            _ => Err(InterpreterError::new("tried to check readiness of non-channel")),
+        }
+    }
+
+    fn pending_count(&self, channel: &Value, globals: &Globals) -> Result<usize, InterpreterError> {
+        match channel {
+            Value::TaskReference(id, _) => Ok(self.get_receiver_from_task(id)?.len()),
+            Value::MagicTaskReference(MagicTask::In) => Ok(globals.input_receiver.len()),
+            Value::Array(items) => items.iter()
+                .map(|item| self.pending_count(item, globals))
+                .sum(),
+
+            _ => Err(InterpreterError::new("tried to count pending values of non-channel")),
+        }
+    }
+
+    /// Assigns `value` to the locals named in `pattern`, which is either an identifier, or an array
+    /// of patterns like `[a, b]` to take apart an array. The last element of an array pattern can
+    /// be `rest..`, to collect any remaining elements.
+    fn destructure(&mut self, pattern: &Node, value: Value, globals: &Globals) -> Result<(), InterpreterError> {
+        let patterns = match &pattern.kind {
+            NodeKind::Identifier(name) => {
+                self.assign_local(name, value, globals);
+                return Ok(())
+            }
+            NodeKind::ArrayLiteral(patterns) => patterns,
+            _ => return Err(InterpreterError::new("expected identifier for result of assign")),
+        };
+
+        let Value::Array(items) = value else {
+            return Err(InterpreterError::new(format!("cannot destructure {} as an array", value.to_printable_string())))
+        };
+        let (fixed, rest) = match patterns.split_last() {
+            Some((Node { kind: NodeKind::Range { begin: Some(rest), end: None } }, fixed)) => (fixed, Some(rest)),
+            _ => (patterns.as_slice(), None),
+        };
+        match rest {
+            Some(_) if items.len() < fixed.len() => return Err(InterpreterError::new(format!(
+                "expected an array of at least length {} to destructure, but found length {}", fixed.len(), items.len()))),
+            None if items.len() != fixed.len() => return Err(InterpreterError::new(format!(
+                "expected an array of length {} to destructure, but found length {}", fixed.len(), items.len()))),
+            _ => (),
+        }
+
+        let mut items = items.into_iter();
+        for (pattern, item) in fixed.iter().zip(items.by_ref()) {
+            self.destructure(pattern, item, globals)?;
+        }
+        if let Some(rest) = rest {
+            self.destructure(rest, Value::Array(items.collect()), globals)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn binary_operation(op: BinaryOperator, left: Value, right: Value) -> Result<Value, InterpreterError> {
+        // Equality works on any values, so check it before requiring integers
+        if op == BinaryOperator::Equals {
+            return Ok(Value::Boolean(left == right))
+        }
+        if op == BinaryOperator::NotEquals {
+            return Ok(Value::Boolean(left != right))
+        }
+        if op == BinaryOperator::In {
+            return Self::contains(&right, &left).map(Value::Boolean)
+        }
+        if op == BinaryOperator::And || op == BinaryOperator::Or {
+            return Ok(Value::Boolean(if op == BinaryOperator::And {
+                left.is_truthy() && right.is_truthy()
+            } else {
+                left.is_truthy() || right.is_truthy()
+            }))
+        }
+
+        // Adding to a string concatenates, converting simple values to strings as they'd be printed
+        if op == BinaryOperator::Add && (matches!(left, Value::String(_)) || matches!(right, Value::String(_))) {
+            let concatenable = |v: &Value| matches!(v, Value::String(_) | Value::Integer(_) | Value::Float(_) | Value::Boolean(_));
+            if !concatenable(&left) || !concatenable(&right) {
+                return Err(InterpreterError::with_kind(InterpreterErrorKind::TypeMismatch,
+                    format!("cannot add {} and {} - only strings, numbers, and booleans can be joined into a string",
+                        left.to_printable_string(), right.to_printable_string())))
+            }
+            return Ok(Value::String(left.to_printable_string() + &right.to_printable_string()))
+        }
+
+        // If either side is a float, the other is converted so that both are
+        if matches!(left, Value::Float(_)) || matches!(right, Value::Float(_)) {
+            let left = left.get_float()?;
+            let right = right.get_float()?;
+
+            return Ok(match op {
+                BinaryOperator::Add         => Value::Float(left + right),
+                BinaryOperator::Subtract    => Value::Float(left - right),
+                BinaryOperator::Multiply    => Value::Float(left * right),
+                BinaryOperator::Divide      => Value::Float(left / right),
+
+                BinaryOperator::LessThan    => Value::Boolean(left < right),
+                BinaryOperator::GreaterThan => Value::Boolean(left > right),
+                BinaryOperator::LessThanOrEqual    => Value::Boolean(left <= right),
+                BinaryOperator::GreaterThanOrEqual => Value::Boolean(left >= right),
+
+                BinaryOperator::Equals
+                | BinaryOperator::NotEquals
+                | BinaryOperator::In
+                | BinaryOperator::And
+                | BinaryOperator::Or        => unreachable!(),
+            })
+        }
+
+        let left = left.get_integer()?;
+        let right = right.get_integer()?;
+
+        if op == BinaryOperator::Divide && right == 0 {
+            return Err(InterpreterError::new("division by zero"))
+        }
+
+        // Results which don't fit in an integer are an error, rather than wrapping around
+        let checked = |result: Option<i64>| result
+            .map(Value::Integer)
+            .ok_or_else(|| InterpreterError::with_kind(InterpreterErrorKind::IntegerOverflow, format!("{left} {op} {right} overflows")));
+
+        Ok(match op {
+            BinaryOperator::Add         => checked(left.checked_add(right))?,
+            BinaryOperator::Subtract    => checked(left.checked_sub(right))?,
+            BinaryOperator::Multiply    => checked(left.checked_mul(right))?,
+            BinaryOperator::Divide      => checked(left.checked_div(right))?,
+
+            BinaryOperator::LessThan    => Value::Boolean(left < right),
+            BinaryOperator::GreaterThan => Value::Boolean(left > right),
+            BinaryOperator::LessThanOrEqual    => Value::Boolean(left <= right),
+            BinaryOperator::GreaterThanOrEqual => Value::Boolean(left >= right),
+
+            BinaryOperator::Equals
+            | BinaryOperator::NotEquals
+            | BinaryOperator::In
+            | BinaryOperator::And
+            | BinaryOperator::Or        => unreachable!(),
+        })
+    }
+
+    fn contains(collection: &Value, item: &Value) -> Result<bool, InterpreterError> {
+        match collection {
+            Value::Array(items) => Ok(items.contains(item)),
+            Value::Map(pairs) => Ok(Value::map_get(pairs, item).is_some()),
+            Value::String(s) => match item {
+                Value::String(item) => Ok(s.contains(item.as_str())),
+                _ => Err(InterpreterError::new(format!("only strings can be in a string, but found {}", item.to_printable_string()))),
+            },
+
+            // Work out whether the item lies between the bounds, rather than going through the
+            // whole range
+            Value::Range { .. } => {
+                let Value::Integer(item) = item else { return Ok(false) };
+                Ok(collection.integer_range()?.contains(*item))
+            }
+
+            _ => Err(InterpreterError::new(format!("`in` expected an array, string, range, or map, but found {}", collection.to_printable_string()))),
+        }
+    }
+
+    fn spawn_anonymous_task(&mut self, body: &Arc<TaskBody>, globals: &Globals) -> Result<Value, InterpreterError> {
+        let id = globals.take_task_id()?;
+        let mut task = TaskState::new(&format!("(anonymous {id})"), id, None, 0);
+        let name = task.formatted_name();
+        globals.task_descriptions_by_id.lock().unwrap().insert(id, name.clone());
+
+        // The new task can only communicate with the task which spawned it
+        let (sender, receiver) = crossbeam_channel::bounded(task.buffer_size);
+        self.senders.insert(id, sender);
+        task.receivers.insert(self.id, receiver);
+        let (sender, receiver) = crossbeam_channel::bounded(self.buffer_size);
+        task.senders.insert(self.id, sender);
+        self.receivers.insert(id, receiver);
+
+        // Keep hold of somewhere to get the task's result, in case it's awaited
+        let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+        self.child_results.insert(id, result_receiver);
+
+        spawn_task(task, body.clone(), globals.clone(), Some(result_sender));
+        Ok(Value::TaskReference(id, name))
+    }
+
+    fn spawn_instances(&mut self, task_name: &str, count: i64, globals: &Globals) -> Result<Value, InterpreterError> {
+        let Some((body, buffer_size)) = globals.task_definitions.get(task_name) else {
+            return Err(InterpreterError::new(format!("cannot spawn `{task_name}`, as there is no task with that name")))
+        };
+        if count < 0 {
+            return Err(InterpreterError::new(format!("cannot spawn a negative number of tasks, but got {count}")))
+        }
+        globals.check_task_limit(count as usize)?;
+        let ids = (0..count).map(|_| globals.take_task_id()).collect::<Result<Vec<_>, _>>()?;
+        let Some(first_id) = ids.first() else { return Ok(Value::Array(vec![])) };
+
+        // The instances need a name which the results won't already have, but should still be
+        // able to wait at a barrier together
+        let group_name = format!("(spawned {task_name} {})", first_id.0);
+        let mut task_globals = globals.clone();
+        task_globals.barriers.insert(group_name.clone(), Arc::new(TaskBarrier::new(ids.len())));
+
+        let mut references = vec![];
+        for (i, id) in ids.into_iter().enumerate() {
+            let mut task = TaskState::new(&group_name, id, Some(i), *buffer_size);
+            let name = task.formatted_name();
+            globals.task_descriptions_by_id.lock().unwrap().insert(id, name.clone());
+
+            // As with anonymous tasks, each instance can only communicate with the task which
+            // spawned it
+            let (sender, receiver) = crossbeam_channel::bounded(task.buffer_size);
+            self.senders.insert(id, sender);
+            task.receivers.insert(self.id, receiver);
+            let (sender, receiver) = crossbeam_channel::bounded(self.buffer_size);
+            task.senders.insert(self.id, sender);
+            self.receivers.insert(id, receiver);
+
+            let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+            self.child_results.insert(id, result_receiver);
+
+            spawn_task(task, body.clone(), task_globals.clone(), Some(result_sender));
+            references.push(Value::TaskReference(id, name));
+        }
+
+        Ok(Value::Array(references))
+    }
+
+    /// Counts a newly-built value of `size` elements towards the total this task has allocated,
+    /// failing if the total goes over the memory limit.
+    pub(crate) fn allocate(&mut self, size: usize, globals: &Globals) -> Result<(), InterpreterError> {
+        self.check_allocation(size, globals)?;
+        self.allocated = self.allocated.saturating_add(size);
+        Ok(())
+    }
+
+    /// Checks that allocating `size` more elements would stay within the memory limit, without
+    /// counting them.
+    pub(crate) fn check_allocation(&self, size: usize, globals: &Globals) -> Result<(), InterpreterError> {
+        let Some(limit) = globals.memory_limit else { return Ok(()) };
+        let total = self.allocated.saturating_add(size);
+        if total > limit {
+            return Err(InterpreterError::with_kind(InterpreterErrorKind::MemoryLimitExceeded,
+                format!("task `{}` allocated {total} elements, exceeding the memory limit of {limit}", self.name)));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_cancelled(globals: &Globals) -> Result<(), InterpreterError> {
+        if globals.cancelled.load(AtomicOrdering::Relaxed) {
+            Err(InterpreterError::with_kind(InterpreterErrorKind::Cancelled, "cancelled, since another task failed"))
+        } else if globals.deadline.is_some_and(|deadline| globals.clock.now() >= deadline) {
+            Err(InterpreterError::with_kind(InterpreterErrorKind::DeadlineExceeded, "the program's deadline passed"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends `Done(task)` to every task named in `notify`, to tell them that this task is
+    /// finishing. Tasks which have already finished are skipped.
+    pub(crate) fn notify_peers(&self, globals: &Globals) {
+        let done = Value::Tagged {
+            tag: "Done".to_string(),
+            fields: vec![Value::TaskReference(self.id, globals.task_description(&self.id))],
+        };
+        for name in &self.notify {
+            let ids = match globals.task_values_by_name.get(name) {
+                Some(Value::TaskReference(id, _)) => vec![*id],
+                Some(Value::Array(items)) => items.iter().filter_map(|item| item.get_task_id().ok()).collect(),
+                _ => vec![],
+            };
+            for id in ids {
+                if let Some(sender) = self.senders.get(&id) {
+                    let _ = sender.send(done.clone());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn check_linear_channels(&self, globals: &Globals) -> Result<(), InterpreterError> {
+        if !self.linear {
+            return Ok(())
+        }
+
+        // Anything still buffered would be dropped along with the channel
+        let mut unconsumed: Vec<_> = self.receivers.iter()
+            .filter(|(_, receiver)| !receiver.is_empty())
+            .map(|(id, receiver)| (*id, receiver.len()))
+            .collect();
+        unconsumed.sort_by_key(|(id, _)| id.0);
+
+        match unconsumed.first() {
+            None => Ok(()),
+            Some((id, count)) => Err(InterpreterError::new(format!("finished with {count} unreceived value(s) from {} on a linear channel",
+                globals.task_description(id)))),
+        }
+    }
+
+    fn await_task(&mut self, task: &Value) -> Result<Value, InterpreterError> {
+        let id = task.get_task_id()?;
+
+        // Each result can only be received once
+        let Some(result_receiver) = self.child_results.remove(&id) else {
+            return Err(InterpreterError::new(format!("cannot await {}, since it wasn't started by this task or has already been awaited",
+                task.to_printable_string())))
+        };
+        result_receiver.recv()?
+    }
+
+    fn receive(&mut self, channel: &Node, bind_channel: bool, blocking: bool, deadline: Option<Instant>, globals: &Globals) -> Result<Result<(Option<TaskID>, Value), TryRecvError>, InterpreterError> {
+        // Look up channel to receive on - a binding receive can use any
+        let channel = if bind_channel { None } else { Some(self.evaluate(channel, globals)?) };
+
+        if blocking {
+            let from = match &channel {
+                Some(Value::TaskReference(_, name)) => Some(name.clone()),
+                Some(Value::MagicTaskReference(MagicTask::In)) => Some("$in".to_string()),
+                _ => None,
+            };
+            self.emit_event(globals, TaskEventKind::BlockedOnReceive { from });
+        }
+
+        let received = match channel {
+            None => self.receive_from_any(blocking, deadline, globals).map(|(id, value)| (Some(id), value)),
+
+            Some(Value::TaskReference(id, _)) => {
+                let receiver = self.get_receiver_from_task(&id)?;
+                Self::receive_on(receiver, blocking, deadline, globals.clock.as_ref()).map(|value| (Some(id), value))
+            }
+
+            Some(Value::MagicTaskReference(MagicTask::In)) =>
+                Self::receive_on(&globals.input_receiver, blocking, deadline, globals.clock.as_ref()).map(|value| (None, value)),
+
+            // Receiving from an array of tasks takes turns between them
+            Some(Value::Array(items)) => {
+                let ids_and_receivers = items.iter()
+                    .map(|item| {
+                        let id = item.get_task_id()?;
+                        Ok((id, self.get_receiver_from_task(&id)?.clone()))
+                    })
+                    .collect::<Result<Vec<_>, InterpreterError>>()?;
+                self.receive_round_robin(&ids_and_receivers, blocking, deadline, globals.clock.as_ref())
+                    .map(|(id, value)| (Some(id), value))
+            }
+
+            _ => return Err(InterpreterError::new("tried to receive from non-channel")),
+        };
+
+        if blocking {
+            self.emit_event(globals, TaskEventKind::Unblocked);
+        }
+        Ok(received)
+    }
+
+    pub(crate) fn emit_event(&self, globals: &Globals, kind: TaskEventKind) {
+        if let Some(event_sink) = &globals.event_sink {
+            // Nobody might be listening any more, which is fine
+            let _ = event_sink.send(TaskEvent {
+                task: self.formatted_name(),
+                time: Instant::now(),
+                kind,
+            });
+        }
+    }
+
+    fn replay_receive(&self, event: TraceEvent, globals: &Globals) -> Result<Result<(Option<TaskID>, Value), TryRecvError>, InterpreterError> {
+        // Wait for whichever channel the recorded receive used
+        Ok(match event {
+            TraceEvent::Received(Some(id)) =>
+                Self::receive_on(self.get_receiver_from_task(&id)?, true, None, globals.clock.as_ref()).map(|value| (Some(id), value)),
+            TraceEvent::Received(None) =>
+                Self::receive_on(&globals.input_receiver, true, None, globals.clock.as_ref()).map(|value| (None, value)),
+            TraceEvent::Empty => Err(TryRecvError::Empty),
+            TraceEvent::Closed => Err(TryRecvError::Disconnected),
+
+            _ => return Err(Self::replay_mismatch()),
+        })
+    }
+
+    fn next_replay_event(&mut self, globals: &Globals) -> Result<Option<TraceEvent>, InterpreterError> {
+        let Some(replay) = &globals.replay else {
+            return Ok(None)
+        };
+
+        let event = replay.events.get(&self.id)
+            .and_then(|events| events.get(self.replay_position))
+            .ok_or_else(Self::replay_mismatch)?;
+        self.replay_position += 1;
+        Ok(Some(*event))
+    }
+
+    fn record_trace_event(&self, globals: &Globals, event: TraceEvent) {
+        if let Some(recorder) = &globals.trace_recorder {
+            recorder.lock().unwrap().events.entry(self.id).or_default().push(event);
+        }
+    }
+
+    fn count_message(globals: &Globals) -> Result<(), InterpreterError> {
+        let Some(budget) = globals.message_budget else {
+            return Ok(())
+        };
+
+        let sent = globals.messages_sent.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+        if sent > budget {
+            Err(InterpreterError::with_kind(InterpreterErrorKind::LivelockSuspected,
+                format!("exceeded message budget of {budget} - the program may be stuck exchanging messages forever")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn replay_mismatch() -> InterpreterError {
+        InterpreterError::new("the trace being replayed doesn't match what this task did")
+    }
+
+    /// Running out of time before `deadline` is reported as the channel being empty.
+    fn receive_on(receiver: &Receiver<Value>, blocking: bool, deadline: Option<Instant>, clock: &dyn Clock) -> Result<Value, TryRecvError> {
+        match (blocking, deadline) {
+            (false, _) => receiver.try_recv(),
+            (true, None) => receiver.recv().map_err(|_| TryRecvError::Disconnected),
+            (true, Some(deadline)) => Self::wait_until(deadline, clock, |timeout| match receiver.recv_timeout(timeout) {
+                Ok(value) => Some(Ok(value)),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => Some(Err(TryRecvError::Disconnected)),
+            })?,
+        }
+    }
+
+    /// Calls `wait` with a timeout until it gives a result, or `clock` reaches `deadline`. The
+    /// clock might not be a real one, so `wait` is given short timeouts rather than the whole time
+    /// left, to notice a mock clock being advanced.
+    fn wait_until<T>(deadline: Instant, clock: &dyn Clock, mut wait: impl FnMut(Duration) -> Option<T>) -> Result<T, TryRecvError> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        loop {
+            let remaining = deadline.saturating_duration_since(clock.now());
+            if remaining.is_zero() {
+                return Err(TryRecvError::Empty)
+            }
+            if let Some(result) = wait(remaining.min(POLL_INTERVAL)) {
+                return Ok(result)
+            }
+        }
+    }
+
+    fn receive_from_any(&self, blocking: bool, deadline: Option<Instant>, globals: &Globals) -> Result<(TaskID, Value), TryRecvError> {
+        // Tasks which have finished will have closed their channel, so skip those and try again
+        let mut closed_ids = vec![];
+        loop {
+            let ids_and_receivers: Vec<_> = self.receivers.iter()
+                .filter(|(id, _)| !closed_ids.contains(*id))
+                .collect();
+            if ids_and_receivers.is_empty() {
+                return Err(TryRecvError::Disconnected)
+            }
+
+            // If any tasks have priorities, take a waiting value from the highest-priority task
+            // first - otherwise, `Select` picks randomly to be fair
+            if !globals.priorities.is_empty() {
+                let mut waiting: Vec<_> = ids_and_receivers.iter()
+                    .filter(|(_, chan)| !chan.is_empty())
+                    .collect();
+                waiting.sort_by_key(|(id, _)| Reverse(globals.priorities.get(id).copied().unwrap_or(0)));
+                if let Some(value) = waiting.iter().find_map(|(id, chan)| chan.try_recv().ok().map(|value| (**id, value))) {
+                    return Ok(value)
+                }
+            }
+
+            let mut selector = Select::new();
+            for (_, chan) in &ids_and_receivers {
+                selector.recv(chan);
+            }
+            let selected = match (blocking, deadline) {
+                (false, _) => selector.try_select().map_err(|_| TryRecvError::Empty)?,
+                (true, None) => selector.select(),
+
+                // Only waiting for a channel to be ready can be done in short bursts
+                (true, Some(deadline)) => {
+                    let index = Self::wait_until(deadline, globals.clock.as_ref(), |timeout| selector.ready_timeout(timeout).ok())?;
+                    let (received_from, received_on_chan) = ids_and_receivers[index];
+                    match received_on_chan.try_recv() {
+                        Ok(received_value) => return Ok((*received_from, received_value)),
+                        Err(TryRecvError::Disconnected) => closed_ids.push(*received_from),
+                        Err(TryRecvError::Empty) => (),
+                    }
+                    continue;
+                }
+            };
+
+            // Figure out which channel we received from
+            let (received_from, received_on_chan) = ids_and_receivers[selected.index()];
+            match selected.recv(received_on_chan) {
+                Ok(received_value) => return Ok((*received_from, received_value)),
+                Err(_) => closed_ids.push(*received_from),
+            }
+        }
+    }
+
+    fn receive_round_robin(&mut self, ids_and_receivers: &[(TaskID, Receiver<Value>)], blocking: bool, deadline: Option<Instant>, clock: &dyn Clock) -> Result<(TaskID, Value), TryRecvError> {
+        // Start looking from just after whichever channel in this array we last received from
+        let ids: Vec<_> = ids_and_receivers.iter().map(|(id, _)| *id).collect();
+        let cursor = self.round_robin_cursors.get(&ids).copied().unwrap_or(0);
+        let order: Vec<_> = (0..ids.len())
+            .map(|i| (cursor + i) % ids.len())
+            .collect();
+
+        let mut closed = vec![false; ids.len()];
+        loop {
+            // Take the first channel in turn which already has a value waiting
+            for &i in &order {
+                if closed[i] {
+                    continue;
+                }
+                match ids_and_receivers[i].1.try_recv() {
+                    Ok(value) => {
+                        self.round_robin_cursors.insert(ids, i + 1);
+                        return Ok((ids_and_receivers[i].0, value))
+                    }
+                    Err(TryRecvError::Disconnected) => closed[i] = true,
+                    Err(TryRecvError::Empty) => (),
+                }
+            }
+
+            if closed.iter().all(|c| *c) {
+                return Err(TryRecvError::Disconnected)
+            }
+            if !blocking {
+                return Err(TryRecvError::Empty)
+            }
+
+            // Nothing is waiting yet, so block until anything is
+            let open: Vec<_> = order.iter().copied().filter(|i| !closed[*i]).collect();
+            let mut selector = Select::new();
+            for &i in &open {
+                selector.recv(&ids_and_receivers[i].1);
+            }
+            // With a deadline, just wait until something is ready, and take it next time round
+            if let Some(deadline) = deadline {
+                Self::wait_until(deadline, clock, |timeout| selector.ready_timeout(timeout).ok())?;
+                continue;
+            }
+            let selected = selector.select();
+            let i = open[selected.index()];
+            match selected.recv(&ids_and_receivers[i].1) {
+                Ok(value) => {
+                    self.round_robin_cursors.insert(ids, i + 1);
+                    return Ok((ids_and_receivers[i].0, value))
+                }
+                Err(_) => closed[i] = true,
+            }
+        }
+    }
+
+    fn record_failed_try_receive(&mut self, globals: &Globals) {
+        self.failed_try_receives += 1;
+
+        // Warn once, when we hit the threshold
+        if Some(self.failed_try_receives) == globals.busy_wait_warning_threshold {
+            self.warn(globals, format!(
+                "{} failed try-receives in a row - this looks like a busy-wait loop, consider using a blocking receive",
+                self.failed_try_receives,
+            ));
+        }
+    }
+
+    fn warn(&self, globals: &Globals, message: impl Into<String>) {
+        let message = format!("{}: {}", self.formatted_name(), message.into());
+        eprintln!("Warning: {message}");
+
+        // The runtime may have already stopped collecting warnings, which is fine
+        let _ = globals.warning_sender.send(message);
+    }
+
+    /// Handles any `break` or `continue` after the body of the loop with this label has run,
+    /// returning whether the loop should stop.
+    fn finish_iteration(&mut self, label: &Option<String>) -> bool {
+        // A `break` or `continue` for an outer loop is left for that loop to handle
+        match self.loop_exit.take() {
+            Some(exit) if !exit.applies_to(label) => {
+                self.loop_exit = Some(exit);
+                true
+            }
+            Some(LoopExit::Break(_)) => true,
+            Some(LoopExit::Continue(_)) | None => false,
+        }
+    }
+
+    pub(crate) fn assign_local(&mut self, name: &str, value: Value, globals: &Globals) {
+        // Globals are copied into each task, so this won't change what other tasks see
+        if !self.locals.contains_key(name) && globals.task_values_by_name.contains_key(name) {
+            self.warn(globals, format!(
+                "assigning to `{name}` creates a local which hides the global of the same name - other tasks won't see this change",
+            ));
+        }
+        self.create_or_assign_local(name, value);
+    }
+
+    pub(crate) fn create_or_assign_local(&mut self, name: &str, value: Value) {
+        // `_` is a discard, so assigning to it drops the value
+        if name == "_" {
+            return
+        }
+
         if let Some(local) = self.locals.get_mut(name) {
             *local = value;
         } else {
@@ -347,7 +1660,7 @@ impl TaskState {
             .ok_or_else(|| InterpreterError::new(format!("no sender for task ID {id}")))
     }
 
-    fn get_receiver_from_task(&self, id: &TaskID) -> Result<&Receiver<Value>, InterpreterError> {
+    pub(crate) fn get_receiver_from_task(&self, id: &TaskID) -> Result<&Receiver<Value>, InterpreterError> {
         self.receivers.get(id)
             .ok_or_else(|| InterpreterError::new(format!("no receiver for task ID {id}")))
     }
@@ -360,9 +1673,55 @@ impl TaskState {
         }
     }
 
-    fn wrap_as_index(mut index: i64, len: usize) -> usize {
+    fn index_range(range: IntegerRange, index: Value) -> Result<Value, InterpreterError> {
+        let Some(len) = range.len() else {
+            return Err(InterpreterError::new("only ranges with a beginning and an end can be indexed"))
+        };
+
+        match index {
+            Value::Integer(index) => {
+                let wrapped = Self::wrap_as_index(index, len);
+                if wrapped < len {
+                    Ok(Value::Integer(range.nth(wrapped)))
+                } else {
+                    Err(InterpreterError::new(format!("index {index} is out of range")))
+                }
+            }
+
+            Value::Range { begin: index_begin, end: index_end } => {
+                let (begin_val, end_val) = Self::slice_bounds(&index_begin, &index_end, len)?;
+
+                if begin_val <= end_val && end_val <= len {
+                    Ok(Value::Range {
+                        begin: Box::new(Value::Integer(range.nth(begin_val))),
+                        end: Box::new(Value::Integer(range.nth(end_val))),
+                    })
+                } else {
+                    Err(InterpreterError::new(format!("indeces {} .. {} are out of range",
+                        index_begin.to_printable_string(), index_end.to_printable_string())))
+                }
+            }
+
+            _ => Err(InterpreterError::new("expected integer or range as index".to_string()))
+        }
+    }
+
+    pub(crate) fn slice_bounds(begin: &Value, end: &Value, len: usize) -> Result<(usize, usize), InterpreterError> {
+        // A missing bound means the start or end of the whole thing
+        let begin = match begin {
+            Value::Null => 0,
+            _ => Self::wrap_as_index(begin.get_integer()?, len),
+        };
+        let end = match end {
+            Value::Null => len,
+            _ => Self::wrap_as_index(end.get_integer()?, len),
+        };
+        Ok((begin, end))
+    }
+
+    pub(crate) fn wrap_as_index(mut index: i64, len: usize) -> usize {
         if index < 0 {
-            index = len as i64 + index;
+            index += len as i64;
         }
         index as usize
     }
@@ -0,0 +1,209 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use crate::interpreter::Value;
+
+/// How deeply arrays and objects can be nested. Each level is parsed recursively, so this stops
+/// deeply nested input from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Parses JSON text into a value. Objects become maps, and numbers become integers if they're
+/// written without a fraction or exponent and fit in one, or floats otherwise.
+pub(crate) fn parse_json(input: &str) -> Result<Value, String> {
+    let mut parser = JsonParser { input, chars: input.char_indices().peekable(), depth: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(i, c)) => Err(format!("unexpected `{c}` after the value, at position {i}")),
+    }
+}
+
+struct JsonParser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some((i, c @ ('{' | '['))) => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(format!("nesting too deep at position {i}"))
+                }
+                self.depth += 1;
+                let result = if c == '{' { self.parse_object() } else { self.parse_array() };
+                self.depth -= 1;
+                result
+            }
+            Some((_, '"')) => self.parse_string().map(Value::String),
+            Some((_, '-' | '0'..='9')) => self.parse_number(),
+            Some((_, 't')) => self.parse_word("true", Value::Boolean(true)),
+            Some((_, 'f')) => self.parse_word("false", Value::Boolean(false)),
+            Some((_, 'n')) => self.parse_word("null", Value::Null),
+            Some((i, c)) => Err(format!("unexpected `{c}` at position {i}")),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+
+        // Later duplicate keys replace earlier ones, like `to_map`
+        let mut pairs: Vec<(Value, Value)> = vec![];
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::Map(pairs))
+        }
+        loop {
+            self.skip_whitespace();
+            let key = Value::String(self.parse_string()?);
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            match pairs.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, existing)) => *existing = value,
+                None => pairs.push((key, value)),
+            }
+
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Map(pairs))
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Value::Array(items))
+        }
+        loop {
+            items.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::Array(items))
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+
+        let mut buffer = String::new();
+        loop {
+            let Some((i, c)) = self.chars.next() else {
+                return Err("unterminated string".to_string())
+            };
+            match c {
+                '"' => return Ok(buffer),
+                '\\' => match self.chars.next() {
+                    Some((_, '"')) => buffer.push('"'),
+                    Some((_, '\\')) => buffer.push('\\'),
+                    Some((_, '/')) => buffer.push('/'),
+                    Some((_, 'b')) => buffer.push('\u{8}'),
+                    Some((_, 'f')) => buffer.push('\u{c}'),
+                    Some((_, 'n')) => buffer.push('\n'),
+                    Some((_, 'r')) => buffer.push('\r'),
+                    Some((_, 't')) => buffer.push('\t'),
+                    Some((_, 'u')) => buffer.push(self.parse_unicode_escape(i)?),
+                    _ => return Err(format!("invalid escape sequence at position {i}")),
+                },
+                c if c.is_control() => return Err(format!("unescaped control character at position {i}")),
+                c => buffer.push(c),
+            }
+        }
+    }
+
+    /// Parses the hex digits of a `\u` escape, including the second half of a surrogate pair.
+    fn parse_unicode_escape(&mut self, position: usize) -> Result<char, String> {
+        let invalid = || format!("invalid unicode escape at position {position}");
+
+        let high = self.parse_hex4().ok_or_else(invalid)?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !(self.eat('\\') && self.eat('u')) {
+                return Err(invalid())
+            }
+            let low = self.parse_hex4().ok_or_else(invalid)?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(invalid())
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(invalid)
+    }
+
+    fn parse_hex4(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let (_, c) = self.chars.next()?;
+            code = code * 16 + c.to_digit(16)?;
+        }
+        Some(code)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.input.len());
+        let mut is_float = false;
+        while let Some(&(_, c)) = self.chars.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => (),
+                '.' | 'e' | 'E' => is_float = true,
+                _ => break,
+            }
+            self.chars.next();
+        }
+        let end = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.input.len());
+        let text = &self.input[start..end];
+
+        let invalid = || format!("invalid number `{text}` at position {start}");
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        if digits.is_empty() || !digits.starts_with(|c: char| c.is_ascii_digit())
+            || (digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            return Err(invalid())
+        }
+
+        // Integers which are too big to fit are kept as floats, rather than being an error
+        if !is_float {
+            if let Ok(i) = text.parse::<i64>() {
+                return Ok(Value::Integer(i))
+            }
+        }
+        text.parse::<f64>().map(Value::Float).map_err(|_| invalid())
+    }
+
+    fn parse_word(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        let start = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.input.len());
+        for expected in word.chars() {
+            if !self.eat(expected) {
+                return Err(format!("unexpected input at position {start}"))
+            }
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r')).is_some() {}
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, c)) => Err(format!("expected `{expected}` but found `{c}` at position {i}")),
+            None => Err(format!("expected `{expected}` but reached the end of input")),
+        }
+    }
+}
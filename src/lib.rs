@@ -1,25 +1,89 @@
-use std::{thread, collections::HashMap, process::exit};
+use std::{collections::HashMap, hash::{Hash, Hasher}, io::BufRead, time::Duration};
 
-use interpreter::{TaskState, TaskID, Globals, Value, InterpreterError};
-use node::{Node, NodeKind};
+use interpreter::{Value, InterpreterError};
 
-use crate::{node::{BinaryOperator, ItemKind}, tokenizer::Tokenizer, parser::Parser, runtime::Runtime};
+use crate::{node::{Item, ItemKind}, tokenizer::{Tokenizer, TokenizerError}, parser::{Parser, ParserError}, runtime::Runtime, analysis::Diagnostics};
 
 pub mod node;
 pub mod interpreter;
 pub mod parser;
 pub mod tokenizer;
 pub mod runtime;
+pub mod builtins;
+pub mod analysis;
+pub mod trace;
+pub mod bytecode;
+pub mod clock;
+pub mod barrier;
+mod json;
+
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// See `Runtime::set_max_evaluation_depth`.
+    pub max_evaluation_depth: Option<usize>,
+
+    /// See `Runtime::enable_bytecode`.
+    pub bytecode: bool,
+
+    /// See `Runtime::set_max_tasks`.
+    pub max_tasks: Option<usize>,
+
+    /// See `Runtime::set_memory_limit`.
+    pub memory_limit: Option<usize>,
+
+    /// See `Runtime::set_message_budget`.
+    pub message_budget: Option<usize>,
+
+    /// See `Runtime::set_deadline`.
+    pub deadline: Option<Duration>,
+}
 
 pub fn run_code(input: &str) -> Option<HashMap<String, Result<Value, InterpreterError>>> {
+    run_code_with_options(input, RunOptions::default())
+}
+
+pub fn run_code_with_options(input: &str, options: RunOptions) -> Option<HashMap<String, Result<Value, InterpreterError>>> {
+    run(create_runtime_with_options(input, &options)?)
+}
+
+/// Like `run_code_with_options`, but reads the program as it's tokenized, rather than needing all of
+/// its text in memory at once. This is only worthwhile for very large programs, since the program's
+/// tokens (which take more memory than its text) are still all kept.
+pub fn run_code_from_reader(input: impl BufRead, options: RunOptions) -> Option<HashMap<String, Result<Value, InterpreterError>>> {
+    run(create_runtime_from_reader(input, &options)?)
+}
+
+fn run(mut runtime: Runtime) -> Option<HashMap<String, Result<Value, InterpreterError>>> {
+    runtime.create_task_channels();
+    runtime.start();
+    Some(runtime.join())
+}
+
+#[derive(Debug, Clone)]
+pub enum CompileError {
+    Tokenizer(TokenizerError),
+    Parser(ParserError),
+}
+
+impl CompileError {
+    pub fn message(&self) -> &str {
+        match self {
+            CompileError::Tokenizer(e) => e.message(),
+            CompileError::Parser(e) => e.message(),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Item>, Vec<CompileError>> {
+    parse_tokenizer(Tokenizer::from_chars(input.chars()))
+}
+
+fn parse_tokenizer(mut tokenizer: Tokenizer) -> Result<Vec<Item>, Vec<CompileError>> {
     // Tokenize
-    let input_chars: Vec<_> = input.chars().collect();
-    let mut tokenizer = Tokenizer::new(&input_chars);
     tokenizer.tokenize();
-    
+
     if !tokenizer.errors.is_empty() {
-        println!("Errors: {:#?}", tokenizer.errors);
-        return None;
+        return Err(tokenizer.errors.into_iter().map(CompileError::Tokenizer).collect());
     }
 
     // Parse
@@ -27,20 +91,162 @@ pub fn run_code(input: &str) -> Option<HashMap<String, Result<Value, Interpreter
     parser.parse_top_level();
 
     if !parser.errors.is_empty() {
-        println!("Errors: {:#?}", parser.errors);
-        return None;
+        return Err(parser.errors.into_iter().map(CompileError::Parser).collect());
     }
 
-    // Create a runtime with tasks
+    Ok(parser.items)
+}
+
+pub fn create_runtime(input: &str) -> Option<Runtime> {
+    create_runtime_with_options(input, &RunOptions::default())
+}
+
+pub fn create_runtime_with_options(input: &str, options: &RunOptions) -> Option<Runtime> {
+    create_runtime_from_items(parse(input), options)
+}
+
+/// See `run_code_from_reader`.
+pub fn create_runtime_from_reader(input: impl BufRead, options: &RunOptions) -> Option<Runtime> {
+    create_runtime_from_items(parse_tokenizer(Tokenizer::from_reader(input)), options)
+}
+
+fn create_runtime_from_items(items: Result<Vec<Item>, Vec<CompileError>>, options: &RunOptions) -> Option<Runtime> {
+    let items = match items {
+        Ok(items) => items,
+        Err(errors) => {
+            println!("Errors: {errors:#?}");
+            return None;
+        }
+    };
+
+    // Create a runtime with tasks - the options are applied first, so that they're enforced while
+    // adding tasks too
     let mut runtime = Runtime::new();
-    for item in parser.items {
+    if let Some(depth) = options.max_evaluation_depth {
+        runtime.set_max_evaluation_depth(depth);
+    }
+    if options.bytecode {
+        runtime.enable_bytecode();
+    }
+    if let Some(max_tasks) = options.max_tasks {
+        runtime.set_max_tasks(max_tasks);
+    }
+    if let Some(limit) = options.memory_limit {
+        runtime.set_memory_limit(limit);
+    }
+    if let Some(budget) = options.message_budget {
+        runtime.set_message_budget(budget);
+    }
+    if let Some(deadline) = options.deadline {
+        runtime.set_deadline(deadline);
+    }
+
+    for item in items {
         match item.kind {
-            ItemKind::TaskDefinition { name, body, instances } => runtime.add_task(&name, body, instances),
+            ItemKind::TaskDefinition { name, body, instances, arguments, buffer_size, linear, priority, notify, .. } => {
+                let arguments = arguments
+                    .map(|args| args.iter().map(|arg| runtime.evaluate_constant(arg)).collect::<Result<Vec<_>, _>>())
+                    .transpose();
+                let arguments = match arguments {
+                    Ok(arguments) => arguments,
+                    Err(e) => {
+                        println!("Error evaluating initializer of task `{name}`: {e:?}");
+                        return None;
+                    }
+                };
+
+                if let Err(e) = runtime.add_task(&name, body, instances, arguments, buffer_size) {
+                    println!("Error adding task `{name}`: {e:?}");
+                    return None;
+                }
+                if linear {
+                    runtime.set_linear(&name);
+                }
+                if priority != 0 {
+                    runtime.set_priority(&name, priority);
+                }
+                if !notify.is_empty() {
+                    runtime.set_notify(&name, &notify);
+                }
+            }
+            ItemKind::ConstantDefinition { name, value } => {
+                if let Err(e) = runtime.add_constant(&name, &value) {
+                    println!("Error evaluating constant `{name}`: {e:?}");
+                    return None;
+                }
+            }
         }
     }
 
-    // Run!
-    runtime.create_task_channels();
-    runtime.start();
-    Some(runtime.join())
+    Some(runtime)
+}
+
+/// The name of each task a program defines, and how many instances it has if it's a multi-task.
+pub fn task_summary(input: &str) -> Result<Vec<(String, Option<usize>)>, Vec<CompileError>> {
+    Ok(parse(input)?.into_iter()
+        .filter_map(|item| match item.kind {
+            ItemKind::TaskDefinition { name, instances, .. } => Some((name, instances)),
+            ItemKind::ConstantDefinition { .. } => None,
+        })
+        .collect())
+}
+
+/// A hash of a program's syntax tree, so that programs which differ only in formatting or comments
+/// hash the same. This doesn't change between runs, but may change between versions of Conker.
+pub fn program_hash(input: &str) -> Result<u64, Vec<CompileError>> {
+    let mut hasher = FnvHasher::default();
+    parse(input)?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// FNV-1a - the standard library's hasher could change between Rust releases.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn check(input: &str) -> Diagnostics {
+    // Tokenize
+    let mut tokenizer = Tokenizer::from_chars(input.chars());
+    tokenizer.tokenize();
+
+    // Parse
+    let mut parser = Parser::new(&tokenizer.tokens);
+    parser.parse_top_level();
+
+    // Run static analysis over whatever we managed to parse, and collect everything together
+    let mut diagnostics = analysis::analyse(&parser.items);
+    let syntax_errors = tokenizer.errors.iter().map(|e| e.message().to_string())
+        .chain(parser.errors.iter().map(|e| e.message().to_string()));
+    diagnostics.errors.splice(0..0, syntax_errors);
+
+    diagnostics
+}
+
+pub fn parse_value(input: &str) -> Option<Value> {
+    let mut tokenizer = Tokenizer::from_chars(input.chars());
+    tokenizer.tokenize();
+    if !tokenizer.errors.is_empty() {
+        return None;
+    }
+
+    let mut parser = Parser::new(&tokenizer.tokens);
+    let expression = parser.parse_standalone_expression()?;
+    Value::from_literal(&expression)
 }
@@ -1,17 +1,52 @@
-use std::{process::exit, env::args, fs};
+use std::{process::exit, env::args, fs::File, io::BufReader};
 
-use conker::run_code;
+use conker::{create_runtime_from_reader, parse_value, RunOptions};
 
 fn main() {
-    let args: Vec<_> = args().collect();
-    if args.len() != 2 {
-        println!("Usage: ... [file]");
+    let mut args: Vec<_> = args().collect();
+
+    // Take out any options, leaving the positional arguments
+    let mut options = RunOptions::default();
+    if let Some(i) = args.iter().position(|arg| arg == "--max-tasks") {
+        let Some(max_tasks) = args.get(i + 1).and_then(|n| n.parse().ok()) else {
+            println!("--max-tasks must be followed by a number");
+            exit(1);
+        };
+        options.max_tasks = Some(max_tasks);
+        args.drain(i..=i + 1);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--memory-limit") {
+        let Some(memory_limit) = args.get(i + 1).and_then(|n| n.parse().ok()) else {
+            println!("--memory-limit must be followed by a number");
+            exit(1);
+        };
+        options.memory_limit = Some(memory_limit);
+        args.drain(i..=i + 1);
+    }
+
+    if args.len() != 2 && args.len() != 3 {
+        println!("Usage: ... [--max-tasks n] [--memory-limit n] [file] [input]");
         exit(1);
     }
     let file = &args[1];
-    let input = fs::read_to_string(file).unwrap();
+    let input = BufReader::new(File::open(file).unwrap());
+
+    let Some(mut runtime) = create_runtime_from_reader(input, &options) else {
+        exit(1);
+    };
+
+    // If an input was given, make it available through `$in`
+    if let Some(input_value) = args.get(2) {
+        let Some(input_value) = parse_value(input_value) else {
+            println!("Input must be a literal value, like an integer or array");
+            exit(1);
+        };
+        runtime.set_input(input_value);
+    }
 
-    for (task, result) in run_code(&input).unwrap().into_iter() {
+    runtime.create_task_channels();
+    runtime.start();
+    for result in runtime.join().into_values() {
         if result.is_err() {
             exit(1);
         }
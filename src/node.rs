@@ -1,3 +1,7 @@
+use std::{fmt::Display, hash::{Hash, Hasher}, mem, sync::Arc};
+
+use crate::runtime::TaskBody;
+
 #[derive(Debug, Clone)]
 pub struct Node {
     pub kind: NodeKind,
@@ -7,6 +11,164 @@ impl Node {
     pub fn new(kind: NodeKind) -> Self {
         Self { kind }
     }
+
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            NodeKind::Body(nodes)
+            | NodeKind::ArrayLiteral(nodes)
+            | NodeKind::Call { arguments: nodes, .. }
+            | NodeKind::Tagged { fields: nodes, .. }
+                => nodes.iter().collect(),
+
+            NodeKind::IntegerLiteral(_)
+            | NodeKind::FloatLiteral(_)
+            | NodeKind::StringLiteral(_)
+            | NodeKind::BooleanLiteral(_)
+            | NodeKind::NullLiteral
+            | NodeKind::Identifier(_)
+            | NodeKind::Break(_)
+            | NodeKind::Continue(_)
+            | NodeKind::Yield
+            | NodeKind::Noop
+            | NodeKind::Barrier
+                => vec![],
+
+            NodeKind::BinaryOperation { left: a, right: b, .. }
+            | NodeKind::While { condition: a, body: b, .. }
+            | NodeKind::For { iterable: a, body: b, .. }
+            | NodeKind::Assign { value: a, destination: b }
+            | NodeKind::Index { value: a, index: b }
+            | NodeKind::Send { value: a, channel: b, .. }
+                => vec![a, b],
+
+            NodeKind::If { condition, if_true, if_false }
+                => [Some(condition), Some(if_true), if_false.as_ref()].into_iter().flatten().map(|n| n.as_ref()).collect(),
+
+            NodeKind::Try { body: a, handler: b, .. }
+                => vec![a, b],
+
+            NodeKind::Range { begin, end }
+                => [begin, end].into_iter().flatten().map(|n| n.as_ref()).collect(),
+
+            NodeKind::UnaryOperation { value, .. }
+            | NodeKind::Lambda { body: value, .. }
+            | NodeKind::Spawn { count: value, .. }
+            | NodeKind::Debug { value, .. }
+                => vec![value],
+
+            NodeKind::Receive { value, channel, default, timeout, .. }
+                => [Some(value), Some(channel), default.as_ref(), timeout.as_ref()].into_iter().flatten().map(|n| n.as_ref()).collect(),
+
+            NodeKind::Flush(channel)
+                => vec![channel],
+
+            NodeKind::AnonymousTask(body)
+                => vec![&body.node],
+
+            NodeKind::Exit(value)
+                => value.iter().map(|n| n.as_ref()).collect(),
+        }
+    }
+}
+
+// Floats can't be hashed, so this is written by hand rather than derived
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(&self.kind).hash(state);
+
+        // Hash everything which isn't a child node...
+        match &self.kind {
+            NodeKind::IntegerLiteral(i) => i.hash(state),
+            NodeKind::FloatLiteral(f) => f.to_bits().hash(state),
+            NodeKind::StringLiteral(s)
+            | NodeKind::Identifier(s)
+            | NodeKind::Call { name: s, .. }
+            | NodeKind::Lambda { parameter: s, .. }
+            | NodeKind::Tagged { tag: s, .. }
+            | NodeKind::Spawn { task: s, .. }
+                => s.hash(state),
+            NodeKind::Debug { .. } => (),
+            NodeKind::For { binding, label, .. } => (binding, label).hash(state),
+            NodeKind::BooleanLiteral(b) => b.hash(state),
+            NodeKind::BinaryOperation { op, .. } => op.hash(state),
+            NodeKind::UnaryOperation { op, .. } => op.hash(state),
+            NodeKind::While { label, .. }
+            | NodeKind::Break(label)
+            | NodeKind::Continue(label)
+            | NodeKind::Try { binding: label, .. }
+                => label.hash(state),
+            NodeKind::Send { try_send, .. } => try_send.hash(state),
+            NodeKind::Receive { bind_channel, default, try_receive, expected_type, timeout, .. } => {
+                (bind_channel, default.is_some(), try_receive, expected_type, timeout.is_some()).hash(state);
+            }
+
+            // ...including which optional children are present, since they're indistinguishable
+            // once collected together
+            NodeKind::Range { begin, end } => (begin.is_some(), end.is_some()).hash(state),
+            NodeKind::If { if_false, .. } => if_false.is_some().hash(state),
+            NodeKind::Exit(value) => value.is_some().hash(state),
+
+            NodeKind::Body(_)
+            | NodeKind::ArrayLiteral(_)
+            | NodeKind::NullLiteral
+            | NodeKind::Yield
+            | NodeKind::Noop
+            | NodeKind::Barrier
+            | NodeKind::Flush(_)
+            | NodeKind::AnonymousTask(_)
+            | NodeKind::Assign { .. }
+            | NodeKind::Index { .. }
+                => (),
+        }
+
+        self.children().hash(state);
+    }
+}
+
+/// Renders an expression roughly as it would have been written, for showing to the user. Nested
+/// operations are always parenthesised, and statements which can't appear in an expression aren't
+/// rendered in full.
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Parenthesise operands which are themselves operations, so precedence doesn't matter
+        let operand = |node: &Node| match node.kind {
+            NodeKind::BinaryOperation { .. } | NodeKind::Assign { .. } | NodeKind::If { .. } => format!("({node})"),
+            _ => node.to_string(),
+        };
+        let list = |nodes: &[Node]| nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ");
+
+        match &self.kind {
+            NodeKind::IntegerLiteral(i) => write!(f, "{i}"),
+            NodeKind::FloatLiteral(x) => write!(f, "{x:?}"),
+            NodeKind::StringLiteral(s) => write!(f, "{s:?}"),
+            NodeKind::BooleanLiteral(b) => write!(f, "{b}"),
+            NodeKind::NullLiteral => write!(f, "null"),
+            NodeKind::ArrayLiteral(items) => write!(f, "[{}]", list(items)),
+            NodeKind::Tagged { tag, fields } => write!(f, "{tag}({})", list(fields)),
+            NodeKind::Range { begin, end } => {
+                let bound = |b: &Option<Box<Node>>| b.as_ref().map(|b| operand(b)).unwrap_or_default();
+                write!(f, "{}..{}", bound(begin), bound(end))
+            }
+
+            NodeKind::Identifier(name) => write!(f, "{name}"),
+            NodeKind::Call { name, arguments } => write!(f, "{name}({})", list(arguments)),
+            NodeKind::Lambda { parameter, body } => write!(f, "{parameter} => {body}"),
+            NodeKind::Spawn { count, task } => write!(f, "spawn {} of {task}", operand(count)),
+
+            NodeKind::BinaryOperation { left, op, right } => write!(f, "{} {op} {}", operand(left), operand(right)),
+            NodeKind::UnaryOperation { op: UnaryOperator::Negate, value } => write!(f, "-{}", operand(value)),
+            NodeKind::UnaryOperation { op, value } => write!(f, "{op} {}", operand(value)),
+
+            NodeKind::If { condition, if_true, if_false: Some(if_false) }
+                => write!(f, "if {condition} then {if_true} else {if_false}"),
+            NodeKind::Assign { value, destination } => write!(f, "{destination} = {value}"),
+            NodeKind::Index { value, index } => write!(f, "{}[{index}]", operand(value)),
+            NodeKind::Debug { value, .. } => write!(f, "debug {value}"),
+
+            NodeKind::AnonymousTask(_) => write!(f, "task: ..."),
+            _ => write!(f, "..."),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -14,29 +176,75 @@ pub enum NodeKind {
     Body(Vec<Node>),
 
     IntegerLiteral(i64),
+    FloatLiteral(f64),
+    StringLiteral(String),
     BooleanLiteral(bool),
     NullLiteral,
     ArrayLiteral(Vec<Node>),
+    Tagged {
+        tag: String,
+        fields: Vec<Node>,
+    },
     Range {
-        begin: Box<Node>,
-        end: Box<Node>,
+        begin: Option<Box<Node>>,
+        end: Option<Box<Node>>,
     },
 
     Identifier(String),
+    Call {
+        name: String,
+        arguments: Vec<Node>,
+    },
+    Lambda {
+        parameter: String,
+        body: Box<Node>,
+    },
+    /// Shared so that starting the same anonymous task again doesn't need to copy or recompile it.
+    AnonymousTask(Arc<TaskBody>),
+    Debug {
+        value: Box<Node>,
+        source: String,
+    },
+    Spawn {
+        count: Box<Node>,
+        task: String,
+    },
 
     BinaryOperation {
         left: Box<Node>,
         op: BinaryOperator,
         right: Box<Node>,
     },
+    UnaryOperation {
+        op: UnaryOperator,
+        value: Box<Node>,
+    },
 
     If {
         condition: Box<Node>,
         if_true: Box<Node>,
+        if_false: Option<Box<Node>>,
     },
     While {
         condition: Box<Node>,
         body: Box<Node>,
+        label: Option<String>,
+    },
+    For {
+        binding: String,
+        iterable: Box<Node>,
+        body: Box<Node>,
+        label: Option<String>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Yield,
+    Noop,
+    Barrier,
+    Try {
+        body: Box<Node>,
+        handler: Box<Node>,
+        binding: Option<String>,
     },
 
     Assign {
@@ -51,17 +259,24 @@ pub enum NodeKind {
     Send {
         value: Box<Node>,
         channel: Box<Node>,
+        try_send: bool,
     },
     Receive {
         value: Box<Node>,
         channel: Box<Node>,
         bind_channel: bool,
+        default: Option<Box<Node>>,
+        try_receive: bool,
+        expected_type: Option<ValueType>,
+        timeout: Option<Box<Node>>,
     },
 
-    Exit,
+    Flush(Box<Node>),
+
+    Exit(Option<Box<Node>>),
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -69,20 +284,124 @@ pub enum BinaryOperator {
     Divide,
 
     Equals,
+    NotEquals,
     LessThan,
     GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    In,
+
+    And,
+    Or,
 }
 
-#[derive(Debug, Clone)]
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Equals => "==",
+            BinaryOperator::NotEquals => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+            BinaryOperator::In => "in",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+        })
+    }
+}
+
+/// A kind of value which a received value can be checked against, with `as`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Integer,
+    Float,
+    Boolean,
+    String,
+    Array,
+    Task,
+    Null,
+    Map,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValueType::Integer => "integer",
+            ValueType::Float => "float",
+            ValueType::Boolean => "boolean",
+            ValueType::String => "string",
+            ValueType::Array => "array",
+            ValueType::Task => "task",
+            ValueType::Null => "null",
+            ValueType::Map => "map",
+        })
+    }
+}
+
+impl ValueType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "integer" => Some(ValueType::Integer),
+            "float" => Some(ValueType::Float),
+            "boolean" => Some(ValueType::Boolean),
+            "string" => Some(ValueType::String),
+            "array" => Some(ValueType::Array),
+            "task" => Some(ValueType::Task),
+            "null" => Some(ValueType::Null),
+            "map" => Some(ValueType::Map),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UnaryOperator {
+    IsNull,
+    Ready,
+    Await,
+    Pending,
+    Negate,
+    Not,
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::IsNull => "isnull",
+            UnaryOperator::Ready => "ready",
+            UnaryOperator::Await => "await",
+            UnaryOperator::Pending => "pending",
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "not",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Hash)]
 pub struct Item {
     pub kind: ItemKind,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum ItemKind {
     TaskDefinition {
         name: String,
         body: Node,
         instances: Option<usize>,
-    }
+        arguments: Option<Vec<Node>>,
+        buffer_size: usize,
+        linear: bool,
+        priority: i64,
+        alternates: Vec<String>,
+        notify: Vec<String>,
+    },
+    ConstantDefinition {
+        name: String,
+        value: Node,
+    },
 }
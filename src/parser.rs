@@ -13,7 +13,9 @@ Syntax example:
     
 */
 
-use crate::{tokenizer::{Token, TokenKind}, node::{Item, Node, NodeKind, ItemKind, BinaryOperator}};
+use std::sync::Arc;
+
+use crate::{tokenizer::{Token, TokenKind}, node::{Item, Node, NodeKind, ItemKind, BinaryOperator, UnaryOperator, ValueType}, runtime::TaskBody};
 
 pub struct Parser<'t> {
     tokens: &'t [Token],
@@ -21,6 +23,9 @@ pub struct Parser<'t> {
 
     pub items: Vec<Item>,
     pub errors: Vec<ParserError>,
+
+    // The labels of the loops enclosing whatever is being parsed, innermost last
+    loop_labels: Vec<Option<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -32,6 +37,10 @@ impl ParserError {
     fn new(s: impl Into<String>) -> Self {
         Self { message: s.into() }
     }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl<'t> Parser<'t> {
@@ -41,6 +50,7 @@ impl<'t> Parser<'t> {
             index: 0,
             items: vec![],
             errors: vec![],
+            loop_labels: vec![],
         }
     }
 
@@ -48,10 +58,17 @@ impl<'t> Parser<'t> {
         self.parse_items();
     }
 
+    pub fn parse_standalone_expression(&mut self) -> Option<Node> {
+        let expression = self.parse_expression()?;
+        self.expect(TokenKind::EndOfFile)?;
+        Some(expression)
+    }
+
     fn parse_items(&mut self) {
         loop {
             match self.this().kind {
                 TokenKind::KwTask => { self.parse_task(); },
+                TokenKind::KwConst => { self.parse_constant(); },
                 TokenKind::NewLine => self.advance(),
                 TokenKind::EndOfFile => break,
                 _ => {
@@ -90,9 +107,100 @@ impl<'t> Parser<'t> {
             self.expect(TokenKind::RightBrace)?;
         }
 
+        // Check for an initializer, giving each instance its own `$arg`
+        let mut arguments = None;
+        if let (Some(instance_count), TokenKind::Assign) = (instances, &self.this().kind) {
+            self.advance();
+            let NodeKind::ArrayLiteral(items) = self.parse_expression()?.kind else {
+                self.errors.push(ParserError::new(format!("initializer for task `{name}` must be an array")));
+                return None;
+            };
+            if items.len() != instance_count {
+                self.errors.push(ParserError::new(format!(
+                    "task `{name}` has {instance_count} instances, but its initializer has {} elements", items.len())));
+            }
+            if !items.iter().all(Self::is_constant) {
+                self.errors.push(ParserError::new(format!("initializer for task `{name}` must not use channels")));
+            }
+            arguments = Some(items);
+        }
+
+        // Check for a buffer on the task's inbound channels
+        let mut buffer_size = 0;
+        if self.this().kind == TokenKind::Identifier("buffer".to_string()) {
+            self.advance();
+            let TokenKind::IntegerLiteral(size) = &self.this().kind else {
+                self.push_unexpected_error(); return None;
+            };
+            if *size < 0 {
+                self.errors.push(ParserError::new("task buffer size cannot be negative"));
+                return None;
+            }
+            buffer_size = *size as usize;
+            self.advance();
+        }
+
+        // Check for a priority, for choosing between tasks which are ready at the same time
+        let mut priority = 0;
+        if self.this().kind == TokenKind::Identifier("priority".to_string()) {
+            self.advance();
+
+            // After an identifier, a `-` is tokenized as a subtraction
+            let negative = self.this().kind == TokenKind::Subtract;
+            if negative {
+                self.advance();
+            }
+            let TokenKind::IntegerLiteral(value) = &self.this().kind else {
+                self.push_unexpected_error(); return None;
+            };
+            priority = if negative { -*value } else { *value };
+            self.advance();
+        }
+
+        // Check whether every value sent to the task must be received
+        let linear = self.this().kind == TokenKind::Identifier("linear".to_string());
+        if linear {
+            self.advance();
+        }
+
+        // Check for peers which the task should alternate between sending to and receiving from
+        let mut alternates = vec![];
+        if self.this().kind == TokenKind::Identifier("alternates".to_string()) {
+            self.advance();
+            alternates = self.parse_task_names()?;
+        }
+
+        // Check for peers to tell when the task finishes
+        let mut notify = vec![];
+        if self.this().kind == TokenKind::Identifier("notify".to_string()) {
+            self.advance();
+            notify = self.parse_task_names()?;
+        }
+
+        // A body can be given on the same line instead, like `task Echo: x <- ?c; x -> c`
+        if self.this().kind == TokenKind::Colon {
+            let body = self.parse_inline_body()?;
+            if self.this().kind != TokenKind::EndOfFile {
+                self.expect(TokenKind::NewLine)?;
+            }
+
+            self.items.push(Item {
+                kind: ItemKind::TaskDefinition { name, body, instances, arguments, buffer_size, linear, priority, alternates, notify }
+            });
+            return Some(())
+        }
+
         // Expect newline, then indentation
         self.expect(TokenKind::NewLine)?;
-        self.expect(TokenKind::Indent)?;
+        if self.this().kind != TokenKind::Indent {
+            // Probably a forgotten indent - skip to the next task, so that it can still be parsed
+            self.errors.push(ParserError::new(format!("expected an indented body after task `{name}`")));
+            while !matches!(self.this().kind, TokenKind::KwTask | TokenKind::EndOfFile) {
+                self.advance();
+            }
+            return None;
+        }
+        self.advance();
 
         // Parse body
         let body = self.parse_body();
@@ -102,11 +210,74 @@ impl<'t> Parser<'t> {
                 name,
                 body,
                 instances,
+                arguments,
+                buffer_size,
+                linear,
+                priority,
+                alternates,
+                notify,
             }
         });
         Some(())
     }
 
+    /// Parses a comma-separated list of task names, like `A, B, C`.
+    fn parse_task_names(&mut self) -> Option<Vec<String>> {
+        let mut names = vec![];
+        loop {
+            let TokenKind::Identifier(name) = &self.this().kind else {
+                self.push_unexpected_error(); return None;
+            };
+            names.push(name.clone());
+            self.advance();
+
+            if self.this().kind != TokenKind::Comma {
+                return Some(names)
+            }
+            self.advance();
+        }
+    }
+
+    fn parse_constant(&mut self) -> Option<()> {
+        // Skip keyword
+        self.expect(TokenKind::KwConst)?;
+
+        // Get name
+        let TokenKind::Identifier(name) = &self.this().kind else {
+            self.push_unexpected_error(); return None;
+        };
+        let name = name.to_string();
+        self.advance();
+
+        // Get value
+        self.expect(TokenKind::Assign)?;
+        let value = self.parse_expression()?;
+        if !Self::is_constant(&value) {
+            self.errors.push(ParserError::new(format!("value of constant `{name}` must not use channels")));
+        }
+        if self.this().kind != TokenKind::EndOfFile {
+            self.expect(TokenKind::NewLine)?;
+        }
+
+        self.items.push(Item {
+            kind: ItemKind::ConstantDefinition { name, value },
+        });
+        Some(())
+    }
+
+    fn is_constant(node: &Node) -> bool {
+        match &node.kind {
+            NodeKind::UnaryOperation { op: UnaryOperator::Ready | UnaryOperator::Await | UnaryOperator::Pending, .. } => false,
+            NodeKind::Identifier(name) if name.starts_with('$') => false,
+            NodeKind::AnonymousTask(_) | NodeKind::Spawn { .. } => false,
+
+            // Builtins which refer to tasks or wait, since there are no tasks to use yet
+            NodeKind::Call { name, .. } if matches!(name.as_str(), "gather" | "ref" | "sleep") => false,
+
+            _ => node.children().into_iter().all(Self::is_constant),
+        }
+    }
+
     fn parse_body(&mut self) -> Node {
         // Build up a body until we hit a dedent
         // (If there is nested indentation, that should be handled by the child parser)
@@ -121,13 +292,56 @@ impl<'t> Parser<'t> {
         Node::new(NodeKind::Body(body_nodes))
     }
 
+    fn parse_inline_body(&mut self) -> Option<Node> {
+        // Statements after a `:` continue to the end of the line, separated by semicolons
+        self.expect(TokenKind::Colon)?;
+
+        let mut body_nodes = vec![self.parse_send_receive()?];
+        while self.this().kind == TokenKind::Semicolon {
+            self.advance();
+            body_nodes.push(self.parse_send_receive()?);
+        }
+
+        Some(Node::new(NodeKind::Body(body_nodes)))
+    }
+
     fn parse_statement(&mut self) -> Option<Node> {
         let stmt = match self.this().kind {
             TokenKind::KwIf => self.parse_if(),
-            TokenKind::KwWhile | TokenKind::KwLoop => self.parse_while(),
+            TokenKind::KwWhile | TokenKind::KwLoop | TokenKind::KwFor => self.parse_while(None),
+            TokenKind::Identifier(ref label) if self.next().kind == TokenKind::Colon => {
+                let label = label.clone();
+                self.advance();
+                self.advance();
+                self.parse_while(Some(label))
+            }
+            TokenKind::KwBreak | TokenKind::KwContinue => self.parse_break_continue(),
+            TokenKind::KwTry => self.parse_try(),
+            TokenKind::KwYield => {
+                self.advance();
+                Some(Node::new(NodeKind::Yield))
+            }
+            TokenKind::KwPass => {
+                self.advance();
+                Some(Node::new(NodeKind::Noop))
+            }
+            TokenKind::KwBarrier => {
+                self.advance();
+                Some(Node::new(NodeKind::Barrier))
+            }
+            TokenKind::KwFlush => {
+                self.advance();
+                Some(Node::new(NodeKind::Flush(Box::new(self.parse_expression()?))))
+            }
             TokenKind::KwExit => {
                 self.advance();
-                Some(Node::new(NodeKind::Exit))
+
+                // An expression after `exit` is optional
+                let value = match self.this().kind {
+                    TokenKind::NewLine | TokenKind::Dedent | TokenKind::EndOfFile => None,
+                    _ => Some(Box::new(self.parse_expression()?)),
+                };
+                Some(Node::new(NodeKind::Exit(value)))
             }
             _ => self.parse_send_receive(),
         };
@@ -144,7 +358,32 @@ impl<'t> Parser<'t> {
         self.expect(TokenKind::KwIf)?;
 
         // Parse condition
-        let condition = self.parse_expression()?;
+        let mut condition = self.parse_expression()?;
+
+        // The condition can be a send or receive, which is always non-blocking - the body only
+        // runs if it succeeded
+        if matches!(self.this().kind,
+            TokenKind::SendArrow | TokenKind::TrySendArrow | TokenKind::ReceiveArrow | TokenKind::TryReceiveArrow)
+        {
+            condition = self.parse_send_receive_operator(condition)?;
+            match &mut condition.kind {
+                NodeKind::Send { try_send, .. } => *try_send = true,
+                NodeKind::Receive { try_receive, timeout, .. } => {
+                    // Parsing the receive didn't know it would be non-blocking, so check this here
+                    if timeout.is_some() {
+                        self.errors.push(ParserError::new("a receive in an `if` condition can't have a timeout, since it doesn't wait"));
+                    }
+                    *try_receive = true;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        // This might actually be a conditional expression, which could be sent somewhere
+        if self.this().kind == TokenKind::KwThen {
+            let conditional = self.parse_conditional_branches(condition)?;
+            return self.parse_send_receive_operator(conditional)
+        }
 
         // Expect newline, then indentation
         self.expect(TokenKind::NewLine)?;
@@ -153,61 +392,233 @@ impl<'t> Parser<'t> {
         // Parse body
         let body = self.parse_body();
 
+        // An `else` at the same indentation has a body which runs if the condition was false.
+        // `else if` continues the chain, by nesting another `if` as that body
+        let mut if_false = None;
+        if self.this().kind == TokenKind::KwElse {
+            self.advance();
+            if self.this().kind == TokenKind::KwIf {
+                if_false = Some(Box::new(self.parse_if()?));
+            } else {
+                self.expect(TokenKind::NewLine)?;
+                self.expect(TokenKind::Indent)?;
+                if_false = Some(Box::new(self.parse_body()));
+            }
+        }
+
         Some(Node::new(NodeKind::If {
             condition: Box::new(condition),
             if_true: Box::new(body),
+            if_false,
+        }))
+    }
+
+    fn parse_conditional_branches(&mut self, condition: Node) -> Option<Node> {
+        // Parse the `then ... else ...` of a conditional expression, after its condition
+        self.expect(TokenKind::KwThen)?;
+        let if_true = self.parse_expression()?;
+        self.expect(TokenKind::KwElse)?;
+        let if_false = self.parse_expression()?;
+
+        Some(Node::new(NodeKind::If {
+            condition: Box::new(condition),
+            if_true: Box::new(if_true),
+            if_false: Some(Box::new(if_false)),
         }))
     }
 
-    fn parse_while(&mut self) -> Option<Node> {
+    fn parse_while(&mut self, label: Option<String>) -> Option<Node> {
         // Skip keyword
-        let condition;
-        match self.this().kind {
+        let condition = match self.this().kind {
             TokenKind::KwWhile => {
                 // Parse condition
                 self.advance();
-                condition = self.parse_expression()?;
+                self.parse_expression()?
             }
 
             TokenKind::KwLoop => {
                 self.advance();
-                condition = Node::new(NodeKind::BooleanLiteral(true));
+                Node::new(NodeKind::BooleanLiteral(true))
             }
 
+            TokenKind::KwFor => return self.parse_for(label),
+
             _ => {
                 self.expect(TokenKind::KwWhile)?;
                 unreachable!();
             }
-        }
+        };
 
         // Expect newline, then indentation
         self.expect(TokenKind::NewLine)?;
         self.expect(TokenKind::Indent)?;
 
         // Parse body
+        self.loop_labels.push(label.clone());
         let body = self.parse_body();
+        self.loop_labels.pop();
 
         Some(Node::new(NodeKind::While {
             condition: Box::new(condition),
             body: Box::new(body),
+            label,
+        }))
+    }
+
+    fn parse_for(&mut self, label: Option<String>) -> Option<Node> {
+        self.expect(TokenKind::KwFor)?;
+        let TokenKind::Identifier(name) = &self.this().kind else {
+            self.push_unexpected_error(); return None;
+        };
+        let name = name.clone();
+        self.advance();
+        self.expect(TokenKind::KwIn)?;
+        let channel = self.parse_expression()?;
+
+        // Without `until`, this iterates over an array or range instead
+        if self.this().kind != TokenKind::Identifier("until".to_string()) {
+            self.expect(TokenKind::NewLine)?;
+            self.expect(TokenKind::Indent)?;
+            self.loop_labels.push(label.clone());
+            let body = self.parse_body();
+            self.loop_labels.pop();
+
+            return Some(Node::new(NodeKind::For {
+                binding: name,
+                iterable: Box::new(channel),
+                body: Box::new(body),
+                label,
+            }))
+        }
+        self.advance();
+        let sentinel = self.parse_expression()?;
+
+        self.expect(TokenKind::NewLine)?;
+        self.expect(TokenKind::Indent)?;
+        self.loop_labels.push(label.clone());
+        let NodeKind::Body(body) = self.parse_body().kind else { unreachable!() };
+        self.loop_labels.pop();
+
+        // `for x in C until s` is a loop which receives `x` from `C`, and breaks once it's `s`
+        let variable = Node::new(NodeKind::Identifier(name));
+        let receive = Node::new(NodeKind::Receive {
+            value: Box::new(variable.clone()),
+            channel: Box::new(channel),
+            bind_channel: false,
+            default: None,
+            try_receive: false,
+            expected_type: None,
+            timeout: None,
+        });
+        let check_sentinel = Node::new(NodeKind::If {
+            condition: Box::new(Node::new(NodeKind::BinaryOperation {
+                left: Box::new(variable),
+                op: BinaryOperator::Equals,
+                right: Box::new(sentinel),
+            })),
+            if_true: Box::new(Node::new(NodeKind::Body(vec![Node::new(NodeKind::Break(label.clone()))]))),
+            if_false: None,
+        });
+
+        Some(Node::new(NodeKind::While {
+            condition: Box::new(Node::new(NodeKind::BooleanLiteral(true))),
+            body: Box::new(Node::new(NodeKind::Body([receive, check_sentinel].into_iter().chain(body).collect()))),
+            label,
+        }))
+    }
+
+    fn parse_try(&mut self) -> Option<Node> {
+        self.expect(TokenKind::KwTry)?;
+        self.expect(TokenKind::NewLine)?;
+        self.expect(TokenKind::Indent)?;
+        let body = self.parse_body();
+
+        // The error can optionally be bound to a local
+        self.expect(TokenKind::KwCatch)?;
+        let mut binding = None;
+        if let TokenKind::Identifier(name) = &self.this().kind {
+            binding = Some(name.clone());
+            self.advance();
+        }
+
+        self.expect(TokenKind::NewLine)?;
+        self.expect(TokenKind::Indent)?;
+        let handler = self.parse_body();
+
+        Some(Node::new(NodeKind::Try {
+            body: Box::new(body),
+            handler: Box::new(handler),
+            binding,
         }))
     }
 
+    fn parse_break_continue(&mut self) -> Option<Node> {
+        let is_break = self.this().kind == TokenKind::KwBreak;
+        let keyword = if is_break { "break" } else { "continue" };
+        self.advance();
+
+        // A label is optional
+        let mut label = None;
+        if let TokenKind::Identifier(name) = &self.this().kind {
+            label = Some(name.clone());
+            self.advance();
+        }
+
+        // Check there's actually a loop for this to refer to
+        match &label {
+            Some(label) if !self.loop_labels.contains(&Some(label.clone())) =>
+                self.errors.push(ParserError::new(format!("`{keyword}` refers to unknown loop label `{label}`"))),
+            None if self.loop_labels.is_empty() =>
+                self.errors.push(ParserError::new(format!("`{keyword}` used outside of a loop"))),
+            _ => (),
+        }
+
+        if is_break {
+            Some(Node::new(NodeKind::Break(label)))
+        } else {
+            Some(Node::new(NodeKind::Continue(label)))
+        }
+    }
+
     fn parse_send_receive(&mut self) -> Option<Node> {
-        let left = self.parse_expression()?;
+        let mut left = self.parse_expression()?;
+
+        // The non-blocking forms evaluate to whether they succeeded, which can be assigned, as in
+        // `sent = x ->? C` - but the assignment will have been parsed as part of the left side
+        let mut result_destination = None;
+        if matches!(self.this().kind, TokenKind::TrySendArrow | TokenKind::TryReceiveArrow) {
+            if let NodeKind::Assign { value, destination } = left.kind {
+                result_destination = Some(destination);
+                left = *value;
+            }
+        }
 
+        let node = self.parse_send_receive_operator(left)?;
+        match result_destination {
+            Some(destination) => Some(Node::new(NodeKind::Assign {
+                value: Box::new(node),
+                destination,
+            })),
+            None => Some(node),
+        }
+    }
+
+    fn parse_send_receive_operator(&mut self, left: Node) -> Option<Node> {
         match self.this().kind {
-            TokenKind::SendArrow => {
+            TokenKind::SendArrow | TokenKind::TrySendArrow => {
+                let try_send = self.this().kind == TokenKind::TrySendArrow;
                 self.advance();
                 let right = self.parse_expression()?;
 
                 Some(Node::new(NodeKind::Send {
                     value: Box::new(left),
                     channel: Box::new(right),
+                    try_send,
                 }))
             }
 
-            TokenKind::ReceiveArrow => {
+            TokenKind::ReceiveArrow | TokenKind::TryReceiveArrow => {
+                let try_receive = self.this().kind == TokenKind::TryReceiveArrow;
                 self.advance();
 
                 let mut bind_channel = false;
@@ -218,10 +629,51 @@ impl<'t> Parser<'t> {
 
                 let right = self.parse_expression()?;
 
+                // Check for a type which the received value must have
+                let mut expected_type = None;
+                if self.this().kind == TokenKind::Identifier("as".to_string()) {
+                    self.advance();
+
+                    // `null` and `task` are keywords, so can't be handled as identifiers like the others
+                    let type_name = match &self.this().kind {
+                        TokenKind::Identifier(name) => name.clone(),
+                        TokenKind::KwNull => "null".to_string(),
+                        TokenKind::KwTask => "task".to_string(),
+                        _ => { self.push_unexpected_error(); return None; }
+                    };
+                    let Some(ty) = ValueType::from_name(&type_name) else {
+                        self.errors.push(ParserError::new(format!("unknown type `{type_name}`")));
+                        return None;
+                    };
+                    expected_type = Some(ty);
+                    self.advance();
+                }
+
+                // Check for a limit on how long to wait, in milliseconds
+                let mut timeout = None;
+                if self.this().kind == TokenKind::Identifier("timeout".to_string()) {
+                    if try_receive {
+                        self.errors.push(ParserError::new("a try-receive can't have a timeout, since it doesn't wait"));
+                    }
+                    self.advance();
+                    timeout = Some(Box::new(self.parse_expression()?));
+                }
+
+                // Check for a default, used if the channel is closed
+                let mut default = None;
+                if self.this().kind == TokenKind::KwOr {
+                    self.advance();
+                    default = Some(Box::new(self.parse_expression()?));
+                }
+
                 Some(Node::new(NodeKind::Receive {
                     value: Box::new(left),
                     channel: Box::new(right),
                     bind_channel,
+                    default,
+                    try_receive,
+                    expected_type,
+                    timeout,
                 }))
             }
 
@@ -234,13 +686,43 @@ impl<'t> Parser<'t> {
     }
 
     fn parse_assign(&mut self) -> Option<Node> {
-        let mut left = self.parse_comparison()?;
+        let mut left = self.parse_logical()?;
 
         while self.this().kind == TokenKind::Assign {
             self.advance();
             left = Node::new(NodeKind::Assign {
                 destination: Box::new(left),
-                value: Box::new(self.parse_comparison()?),
+                value: Box::new(self.parse_logical()?),
+            });
+        }
+
+        Some(left)
+    }
+
+    fn parse_logical(&mut self) -> Option<Node> {
+        let mut left = self.parse_logical_and()?;
+
+        while self.this().kind == TokenKind::LogicalOr {
+            self.advance();
+            left = Node::new(NodeKind::BinaryOperation {
+                left: Box::new(left),
+                op: BinaryOperator::Or,
+                right: Box::new(self.parse_logical_and()?),
+            });
+        }
+
+        Some(left)
+    }
+
+    fn parse_logical_and(&mut self) -> Option<Node> {
+        let mut left = self.parse_comparison()?;
+
+        while self.this().kind == TokenKind::LogicalAnd {
+            self.advance();
+            left = Node::new(NodeKind::BinaryOperation {
+                left: Box::new(left),
+                op: BinaryOperator::And,
+                right: Box::new(self.parse_comparison()?),
             });
         }
 
@@ -260,6 +742,14 @@ impl<'t> Parser<'t> {
                         right: Box::new(self.parse_add_sub()?),
                     });
                 },
+                TokenKind::NotEquals => {
+                    self.advance();
+                    left = Node::new(NodeKind::BinaryOperation {
+                        left: Box::new(left),
+                        op: BinaryOperator::NotEquals,
+                        right: Box::new(self.parse_add_sub()?),
+                    });
+                },
                 TokenKind::LessThan => {
                     self.advance();
                     left = Node::new(NodeKind::BinaryOperation {
@@ -276,6 +766,30 @@ impl<'t> Parser<'t> {
                         right: Box::new(self.parse_add_sub()?),
                     });
                 },
+                TokenKind::LessThanOrEqual => {
+                    self.advance();
+                    left = Node::new(NodeKind::BinaryOperation {
+                        left: Box::new(left),
+                        op: BinaryOperator::LessThanOrEqual,
+                        right: Box::new(self.parse_add_sub()?),
+                    });
+                },
+                TokenKind::GreaterThanOrEqual => {
+                    self.advance();
+                    left = Node::new(NodeKind::BinaryOperation {
+                        left: Box::new(left),
+                        op: BinaryOperator::GreaterThanOrEqual,
+                        right: Box::new(self.parse_add_sub()?),
+                    });
+                },
+                TokenKind::KwIn => {
+                    self.advance();
+                    left = Node::new(NodeKind::BinaryOperation {
+                        left: Box::new(left),
+                        op: BinaryOperator::In,
+                        right: Box::new(self.parse_add_sub()?),
+                    });
+                },
 
                 _ => break,
             }
@@ -343,17 +857,46 @@ impl<'t> Parser<'t> {
     }
 
     fn parse_range(&mut self) -> Option<Node> {
-        let mut left = self.parse_index()?;
+        // Either side of a range can be left out, like `..3` or `2..`
+        let mut left = if self.this().kind == TokenKind::Range {
+            None
+        } else {
+            Some(self.parse_unary()?)
+        };
 
         while self.this().kind == TokenKind::Range {
             self.advance();
-            left = Node::new(NodeKind::Range {
-                begin: Box::new(left),
-                end: Box::new(self.parse_expression()?),
-            });
+
+            let end = match self.this().kind {
+                TokenKind::RightBrace | TokenKind::RightParen | TokenKind::Comma
+                | TokenKind::NewLine | TokenKind::EndOfFile => None,
+                _ => Some(Box::new(self.parse_expression()?)),
+            };
+            left = Some(Node::new(NodeKind::Range {
+                begin: left.map(Box::new),
+                end,
+            }));
         }
 
-        Some(left)
+        left
+    }
+
+    fn parse_unary(&mut self) -> Option<Node> {
+        let op = match self.this().kind {
+            TokenKind::KwIsNull => UnaryOperator::IsNull,
+            TokenKind::KwNot => UnaryOperator::Not,
+            TokenKind::KwReady => UnaryOperator::Ready,
+            TokenKind::KwAwait => UnaryOperator::Await,
+            TokenKind::KwPending => UnaryOperator::Pending,
+            TokenKind::Subtract => UnaryOperator::Negate,
+            _ => return self.parse_index(),
+        };
+        self.advance();
+
+        Some(Node::new(NodeKind::UnaryOperation {
+            op,
+            value: Box::new(self.parse_unary()?),
+        }))
     }
 
     fn parse_index(&mut self) -> Option<Node> {
@@ -390,9 +933,41 @@ impl<'t> Parser<'t> {
     fn parse_atom(&mut self) -> Option<Node> {
         match &self.this().kind {
             TokenKind::Identifier(id) => {
-                let x = Some(Node::new(NodeKind::Identifier(id.clone())));
+                let id = id.clone();
                 self.advance();
-                x
+
+                // An identifier immediately followed by parentheses is a builtin call, or a tagged
+                // value
+                if self.this().kind == TokenKind::LeftParen {
+                    self.advance();
+
+                    let mut arguments = vec![];
+                    while self.this().kind != TokenKind::RightParen {
+                        arguments.push(self.parse_expression()?);
+
+                        if self.this().kind != TokenKind::RightParen {
+                            self.expect(TokenKind::Comma)?;
+                        }
+                    }
+                    self.advance();
+
+                    // Builtins are lowercase, so a capitalised name constructs a tagged value
+                    if id.starts_with(|c: char| c.is_ascii_uppercase()) {
+                        Some(Node::new(NodeKind::Tagged { tag: id, fields: arguments }))
+                    } else {
+                        Some(Node::new(NodeKind::Call { name: id, arguments }))
+                    }
+                } else if self.this().kind == TokenKind::FatArrow {
+                    // An identifier followed by `=>` is a lambda, which builtins can evaluate for
+                    // each item they're working with
+                    self.advance();
+                    Some(Node::new(NodeKind::Lambda {
+                        parameter: id,
+                        body: Box::new(self.parse_expression()?),
+                    }))
+                } else {
+                    Some(Node::new(NodeKind::Identifier(id)))
+                }
             },
 
             TokenKind::IntegerLiteral(int) => {
@@ -400,6 +975,16 @@ impl<'t> Parser<'t> {
                 self.advance();
                 x
             },
+            TokenKind::FloatLiteral(float) => {
+                let x = Some(Node::new(NodeKind::FloatLiteral(*float)));
+                self.advance();
+                x
+            },
+            TokenKind::StringLiteral(string) => {
+                let x = Some(Node::new(NodeKind::StringLiteral(string.clone())));
+                self.advance();
+                x
+            },
             TokenKind::KwTrue => {
                 self.advance();
                 Some(Node::new(NodeKind::BooleanLiteral(true)))
@@ -413,6 +998,44 @@ impl<'t> Parser<'t> {
                 Some(Node::new(NodeKind::NullLiteral))
             }
 
+            TokenKind::KwIf => {
+                self.advance();
+                let condition = self.parse_expression()?;
+                self.parse_conditional_branches(condition)
+            }
+
+            // `task:` starts an anonymous task, whose body is the rest of the line, with
+            // statements separated by semicolons
+            TokenKind::KwTask => {
+                self.advance();
+                let body = self.parse_inline_body()?;
+                Some(Node::new(NodeKind::AnonymousTask(Arc::new(TaskBody::new(body)))))
+            }
+
+            // `debug x` prints `x` along with its value, keeping a rendering of `x` since the
+            // source text isn't available later
+            TokenKind::KwDebug => {
+                self.advance();
+                let value = self.parse_expression()?;
+                let source = value.to_string();
+                Some(Node::new(NodeKind::Debug { value: Box::new(value), source }))
+            }
+
+            // `spawn n of Task` starts more instances of a task definition
+            TokenKind::KwSpawn => {
+                self.advance();
+                let count = self.parse_add_sub()?;
+                self.expect(TokenKind::Identifier("of".to_string()))?;
+
+                let TokenKind::Identifier(task) = &self.this().kind else {
+                    self.push_unexpected_error(); return None;
+                };
+                let task = task.clone();
+                self.advance();
+
+                Some(Node::new(NodeKind::Spawn { count: Box::new(count), task }))
+            }
+
             TokenKind::LeftBrace => {
                 self.advance();
 
@@ -455,9 +1078,13 @@ impl<'t> Parser<'t> {
         }
     }
 
+    fn next(&self) -> &Token {
+        self.tokens.get(self.index + 1).unwrap_or_else(|| self.tokens.last().unwrap())
+    }
+
     #[must_use]
     fn expect(&mut self, kind: TokenKind) -> Option<()> {
-        if &self.this().kind != &kind {
+        if self.this().kind != kind {
             self.push_unexpected_error();
             return None;
         };
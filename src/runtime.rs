@@ -1,87 +1,441 @@
-use std::{collections::HashMap, thread::{JoinHandle, self}, sync::Arc};
+use std::{cmp::Reverse, panic::{self, AssertUnwindSafe}, collections::HashMap, thread, time::{Duration, Instant}, sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicUsize, Ordering}}, io::Write};
 
 use crossbeam_channel::{Receiver, Sender};
 
-use crate::{interpreter::{TaskID, TaskState, Globals, Value, InterpreterError}, node::Node};
+use crate::{barrier::TaskBarrier, clock::{Clock, RealClock}, interpreter::{TaskID, TaskState, Globals, Value, InterpreterError, FormatOptions, OutputWriter, TaskResult}, node::Node, trace::Trace, bytecode::Bytecode};
 
 pub struct Runtime {
     globals: Globals,
-    tasks: Vec<(TaskState, Node)>,
+    tasks: Vec<(TaskState, Arc<TaskBody>)>,
 
-    next_task_id: TaskID,
+    result_receiver: Receiver<TaskResult>,
 
-    result_sender: Sender<(TaskID, String, Result<Value, InterpreterError>)>,
-    result_receiver: Receiver<(TaskID, String, Result<Value, InterpreterError>)>,
+    task_complete_callback: Option<TaskCompleteCallback>,
+
+    fail_fast: bool,
+    failed_task: Option<String>,
+    deadline: Option<Duration>,
+
+    warning_receiver: Receiver<String>,
+    warnings: Vec<String>,
+}
+
+/// How long sends over a channel spent blocked, waiting for the receiver.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    pub sends: usize,
+    pub total_blocked: Duration,
+    pub max_blocked: Duration,
+}
+
+/// The body of a task definition, which every instance shares. It's only compiled to bytecode if
+/// that's needed, and then only once.
+#[derive(Debug)]
+pub struct TaskBody {
+    pub node: Node,
+    bytecode: OnceLock<Bytecode>,
+}
+
+impl TaskBody {
+    pub fn new(node: Node) -> Self {
+        Self { node, bytecode: OnceLock::new() }
+    }
+
+    pub fn bytecode(&self) -> &Bytecode {
+        self.bytecode.get_or_init(|| Bytecode::compile(&self.node))
+    }
+}
+
+/// The outcome of `Runtime::run_task_isolated`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsolatedTaskRun {
+    pub result: Result<Value, InterpreterError>,
+    pub sent: Vec<Value>,
+    pub locals: Vec<(String, Value)>,
+}
+
+/// Something which happened to a task, sent to the event sink given to `Runtime::set_event_sink`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskEvent {
+    /// The formatted name of the task, like `Worker[0]`.
+    pub task: String,
+    pub time: Instant,
+    pub kind: TaskEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskEventKind {
+    Started,
+    /// The task is waiting on a blocking receive. `from` is the name of the task it's receiving
+    /// from, or `None` if it could receive from several, like with a binding receive.
+    BlockedOnReceive { from: Option<String> },
+    /// The task's blocking receive has finished.
+    Unblocked,
+    Completed(Result<Value, InterpreterError>),
+}
+
+pub type TaskCompleteCallback = Box<dyn FnMut(&str, &Result<Value, InterpreterError>)>;
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Runtime {
     pub fn new() -> Self {
         let (result_sender, result_receiver) = crossbeam_channel::unbounded();
+        let (warning_sender, warning_receiver) = crossbeam_channel::unbounded();
+
+        // With no input, `$in` is a channel which is already closed
+        let (_, input_receiver) = crossbeam_channel::bounded(0);
 
         Self {
             globals: Globals {
                 task_values_by_name: HashMap::new(),
-                task_descriptions_by_id: HashMap::new(),
+                task_descriptions_by_id: Arc::new(Mutex::new(HashMap::new())),
+
+                next_task_id: Arc::new(AtomicUsize::new(1)),
+                started_task_count: Arc::new(AtomicUsize::new(0)),
+                result_sender,
+
+                input_receiver,
+                warning_sender,
+                busy_wait_warning_threshold: None,
+                max_evaluation_depth: None,
+                channel_metrics: None,
+                format_options: FormatOptions::default(),
+                output_writer: None,
+                trace_recorder: None,
+                replay: None,
+                bytecode: false,
+                max_tasks: None,
+                memory_limit: None,
+                barriers: HashMap::new(),
+                task_definitions: HashMap::new(),
+                priorities: HashMap::new(),
+                message_budget: None,
+                messages_sent: Arc::new(AtomicUsize::new(0)),
+                event_sink: None,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                deadline: None,
+                clock: Arc::new(RealClock),
             },
             tasks: vec![],
-            next_task_id: TaskID(1),
 
-            result_sender,
-            result_receiver
+            result_receiver,
+
+            task_complete_callback: None,
+
+            fail_fast: false,
+            failed_task: None,
+            deadline: None,
+
+            warning_receiver,
+            warnings: vec![],
         }
     }
     
-    pub fn add_task(&mut self, name: &str, body: Node, instances: Option<usize>) {
+    /// Registers a callback which is invoked by `join` each time a task completes, with the task's
+    /// name and outcome. It runs on the joining thread, not the task's own thread.
+    pub fn on_task_complete(&mut self, callback: impl FnMut(&str, &Result<Value, InterpreterError>) + 'static) {
+        self.task_complete_callback = Some(Box::new(callback));
+    }
+
+    /// Provides a value which can be received once, by any task, from `$in`.
+    pub fn set_input(&mut self, value: Value) {
+        let (input_sender, input_receiver) = crossbeam_channel::bounded(1);
+        input_sender.send(value).unwrap();
+        self.globals.input_receiver = input_receiver;
+    }
+
+    /// Enables a warning when a task performs this many failed try-receives in a row, which
+    /// suggests that it is busy-waiting.
+    pub fn set_busy_wait_warning_threshold(&mut self, threshold: usize) {
+        self.globals.busy_wait_warning_threshold = Some(threshold);
+    }
+
+    /// Limits how deeply nested the evaluation of a task can get, before it fails with a
+    /// `RecursionLimit` error rather than overflowing the stack.
+    pub fn set_max_evaluation_depth(&mut self, depth: usize) {
+        self.globals.max_evaluation_depth = Some(depth);
+    }
+
+    /// Limits how many tasks can exist, including instances of multi-tasks and anonymous tasks,
+    /// so that a program can't exhaust the host's threads. Adding or spawning a task beyond the
+    /// limit is an error. Tasks which have already been added aren't affected.
+    pub fn set_max_tasks(&mut self, max_tasks: usize) {
+        self.globals.max_tasks = Some(max_tasks);
+    }
+
+    /// Limits how many elements each task can allocate over its lifetime, counting the items of
+    /// arrays (including nested ones), the entries of maps, and the bytes of strings in every value
+    /// the task builds, so that a task can't exhaust the host's memory. Going over the limit fails
+    /// with a `MemoryLimitExceeded` error.
+    pub fn set_memory_limit(&mut self, elements: usize) {
+        self.globals.memory_limit = Some(elements);
+    }
+
+    /// Sends a `TaskEvent` to `sink` whenever a task starts, blocks on a receive, unblocks, or
+    /// completes. Events from different tasks can arrive slightly out of order, so use their
+    /// timestamps to order them.
+    pub fn set_event_sink(&mut self, sink: Sender<TaskEvent>) {
+        self.globals.event_sink = Some(sink);
+    }
+
+    /// Limits the total number of messages which tasks can send to each other, across all tasks.
+    /// The send which exceeds it fails with a `LivelockSuspected` error, to stop programs which
+    /// would exchange messages forever without finishing. Sends to `$out` don't count.
+    pub fn set_message_budget(&mut self, budget: usize) {
+        self.globals.message_budget = Some(budget);
+    }
+
+    /// Makes `join` return as soon as any task fails, rather than waiting for every task to
+    /// finish. The other tasks are cancelled, failing with a `Cancelled` error the next time they
+    /// evaluate anything - though a task blocked on a receive only notices once it unblocks.
+    pub fn set_fail_fast(&mut self, fail_fast: bool) {
+        self.fail_fast = fail_fast;
+    }
+
+    /// Gives the whole program this long to run, measured from `start`. Once it passes, tasks fail
+    /// with a `DeadlineExceeded` error the next time they evaluate anything, and receives which are
+    /// waiting fail with it straight away - even ones with a longer `timeout` of their own.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Replaces the clock used for receive timeouts, the deadline, and `sleep`, which is normally the
+    /// system's clock. Passing a `MockClock` lets tests control exactly when time passes.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.globals.clock = clock;
+    }
+
+    /// With `set_fail_fast`, the name of the task whose failure stopped the program, if any did.
+    pub fn failed_task(&self) -> Option<&str> {
+        self.failed_task.as_deref()
+    }
+
+    /// Starts timing how long each send blocks for, to find bottlenecks between tasks. This has a
+    /// small cost on every send, so is off by default.
+    pub fn enable_channel_metrics(&mut self) {
+        self.globals.channel_metrics = Some(Arc::new(Mutex::new(HashMap::new())));
+    }
+
+    /// Metrics for each channel which has been sent over, keyed by the names of the sending and
+    /// receiving tasks. Empty unless `enable_channel_metrics` was called before starting.
+    pub fn channel_metrics(&self) -> HashMap<(String, String), ChannelMetrics> {
+        let Some(metrics) = &self.globals.channel_metrics else {
+            return HashMap::new()
+        };
+
+        metrics.lock().unwrap().iter()
+            .map(|((from, to), metrics)| {
+                let from = self.globals.task_description(from);
+                let to = self.globals.task_description(to);
+                ((from, to), metrics.clone())
+            })
+            .collect()
+    }
+
+    /// Sets how values sent to `$out` are printed.
+    pub fn set_format_options(&mut self, options: FormatOptions) {
+        self.globals.format_options = options;
+    }
+
+    /// Sends everything written to `$out` to `writer`, rather than standard output. Each value is
+    /// written as a whole line, so output from different tasks is never interleaved.
+    pub fn set_output_writer(&mut self, writer: Box<dyn Write + Send>) {
+        self.globals.output_writer = Some(OutputWriter(Arc::new(Mutex::new(writer))));
+    }
+
+    /// Starts recording a trace of the decisions made by tasks as they run, which can be retrieved
+    /// with `trace` once they finish.
+    pub fn record_trace(&mut self) {
+        self.globals.trace_recorder = Some(Arc::new(Mutex::new(Trace::default())));
+    }
+
+    /// The trace recorded so far. Empty unless `record_trace` was called before starting.
+    pub fn trace(&self) -> Trace {
+        self.globals.trace_recorder.as_ref()
+            .map(|recorder| recorder.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// Makes every task repeat the decisions recorded in `trace`, reproducing the run it was
+    /// recorded from. The program must be the same one which the trace was recorded from.
+    pub fn replay(&mut self, trace: Trace) {
+        self.globals.replay = Some(Arc::new(trace));
+    }
+
+    /// Compiles each task definition's body to bytecode the first time one of its instances runs,
+    /// and runs that instead of walking its tree. This is faster for tasks which spend a long time computing rather than communicating,
+    /// and behaves identically - except that compiled code doesn't count towards the maximum
+    /// evaluation depth, since it isn't recursive.
+    pub fn enable_bytecode(&mut self) {
+        self.globals.bytecode = true;
+    }
+
+    /// Makes the channels into a task (or each instance of a multi-task) linear - if the task
+    /// finishes while values sent to it are still waiting to be received, it fails.
+    pub fn set_linear(&mut self, name: &str) {
+        for (task, _) in &mut self.tasks {
+            if task.name == name {
+                task.linear = true;
+            }
+        }
+    }
+
+    /// Makes a task (or each instance of a multi-task) send `Done(task)` to each of `peers` when it
+    /// finishes, whether or not it succeeded, so that they can tell it has stopped. This blocks
+    /// until each peer receives it, except for peers which have already finished.
+    pub fn set_notify(&mut self, name: &str, peers: &[String]) {
+        for (task, _) in &mut self.tasks {
+            if task.name == name {
+                task.notify = peers.to_vec();
+            }
+        }
+    }
+
+    /// Gives a task (or each instance of a multi-task) a priority, which is 0 by default. Tasks are
+    /// started in order of priority, and when a task could receive from several others which all
+    /// have a value waiting, it takes the value from whichever has the highest priority.
+    ///
+    /// Tasks are threads, so this is only a hint - it can't stop the operating system from running
+    /// a lower-priority task first.
+    pub fn set_priority(&mut self, name: &str, priority: i64) {
+        for (task, _) in &mut self.tasks {
+            if task.name == name {
+                task.priority = priority;
+                self.globals.priorities.insert(task.id, priority);
+            }
+        }
+    }
+
+    /// Warnings emitted by tasks while running. These are collected by `join`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Adds a task, or a multi-task if `instances` is given. Channels sent into the task hold up to
+    /// `buffer_size` values before sends block.
+    ///
+    /// For a multi-task, `arguments` can give each instance a value for `$arg`, by index.
+    ///
+    /// Fails if there is already a task or constant with the same name.
+    pub fn add_task(&mut self, name: &str, body: Node, instances: Option<usize>, arguments: Option<Vec<Value>>, buffer_size: usize) -> Result<(), InterpreterError> {
+        // Otherwise, the new task would replace the old one, leaving nothing able to reach it
+        self.check_name_available(name)?;
+        self.globals.check_task_limit(instances.unwrap_or(1))?;
+
+        let body = Arc::new(TaskBody::new(body));
         let global_value;
 
         if let Some(instance_count) = instances {
             let mut ids = vec![];
             for i in 0..instance_count {
-                let (id, name) = self.add_one_task(name, body.clone(), Some(i));
+                let (id, name) = self.add_instance(name, body.clone(), Some(i), buffer_size)?;
                 ids.push(Value::TaskReference(id, name));
+
+                if let Some(argument) = arguments.as_ref().and_then(|args| args.get(i)) {
+                    let (task, _) = self.tasks.last_mut().unwrap();
+                    task.locals.insert("$arg".to_string(), argument.clone());
+                }
             }
-            global_value = Value::Array(ids)
+            global_value = Value::Array(ids);
+            self.globals.barriers.insert(name.to_string(), Arc::new(TaskBarrier::new(instance_count)));
         } else {
-            let (id, name) = self.add_one_task(name, body, None);
+            let (id, name) = self.add_instance(name, body.clone(), None, buffer_size)?;
             global_value = Value::TaskReference(id, name);
         }
 
         self.globals.task_values_by_name.insert(name.to_string(), global_value);
+        self.globals.task_definitions.insert(name.to_string(), (body, buffer_size));
+        Ok(())
     }
 
-    pub fn add_one_task(&mut self, name: &str, body: Node, index: Option<usize>) -> (TaskID, String) {
-        let id = self.take_task_id();
-        let state = TaskState {
-            name: name.to_string(),
-            id,
-            index,
-
-            locals: HashMap::new(),
+    pub fn add_one_task(&mut self, name: &str, body: Node, index: Option<usize>, buffer_size: usize) -> Result<(TaskID, String), InterpreterError> {
+        self.add_instance(name, Arc::new(TaskBody::new(body)), index, buffer_size)
+    }
 
-            receivers: HashMap::new(),
-            senders: HashMap::new(),
-        };
+    fn add_instance(&mut self, name: &str, body: Arc<TaskBody>, index: Option<usize>, buffer_size: usize) -> Result<(TaskID, String), InterpreterError> {
+        let id = self.globals.take_task_id()?;
+        let state = TaskState::new(name, id, index, buffer_size);
         let name = state.formatted_name();
-        self.globals.task_descriptions_by_id.insert(id, name.clone());
+        self.globals.task_descriptions_by_id.lock().unwrap().insert(id, name.clone());
         self.tasks.push((state, body));
 
-        (id, name)
+        Ok((id, name))
+    }
+
+    /// Evaluates the value of a constant, which every task can then access by name.
+    pub fn add_constant(&mut self, name: &str, value: &Node) -> Result<(), InterpreterError> {
+        self.check_name_available(name)?;
+
+        let value = self.evaluate_constant(value)?;
+        self.globals.task_values_by_name.insert(name.to_string(), value);
+
+        Ok(())
+    }
+
+    /// Evaluates an expression which doesn't use channels, with access to previously-added
+    /// constants and tasks.
+    pub fn evaluate_constant(&self, value: &Node) -> Result<Value, InterpreterError> {
+        // The expression is evaluated by a task which never runs, so has no channels
+        let mut evaluator = TaskState::new("(constant)", TaskID(0), None, 0);
+        evaluator.evaluate(value, &self.globals)
+    }
+
+    /// Runs one task on the current thread, without any of the others, for testing its logic.
+    ///
+    /// The task's peers are all replaced with a single mock peer. Whichever peer the task receives
+    /// from, it gets the next of the `inputs`, and once they run out the mock peer is finished.
+    /// Everything the task sends, to any peer, is recorded in order, along with its locals once it
+    /// finishes.
+    ///
+    /// The task is identified by its formatted name, like `Worker[0]` for a multi-task instance.
+    /// Returns `None` if there is no such task.
+    pub fn run_task_isolated(&self, name: &str, inputs: Vec<Value>) -> Option<IsolatedTaskRun> {
+        let (task, body) = self.tasks.iter().find(|(task, _)| task.formatted_name() == name)?;
+        let mut task = task.clone();
+
+        let (input_sender, input_receiver) = crossbeam_channel::unbounded();
+        for input in inputs {
+            input_sender.send(input).unwrap();
+        }
+        drop(input_sender);
+        let (sent_sender, sent_receiver) = crossbeam_channel::unbounded();
+
+        // Every peer shares the same channels, so it doesn't matter which the task uses
+        task.receivers.clear();
+        task.senders.clear();
+        for (other, _) in &self.tasks {
+            if other.id != task.id {
+                task.receivers.insert(other.id, input_receiver.clone());
+                task.senders.insert(other.id, sent_sender.clone());
+            }
+        }
+        drop(sent_sender);
+
+        let result = task.evaluate(&body.node, &self.globals);
+        let locals = task.locals_snapshot();
+
+        // Dropping the task closes its senders, so that collecting what was sent doesn't block
+        drop(task);
+
+        Some(IsolatedTaskRun {
+            result,
+            sent: sent_receiver.iter().collect(),
+            locals,
+        })
     }
 
     pub fn start(&mut self) {
-        for (task, body) in &mut self.tasks {
-            let cloned_globals = self.globals.clone();
-            let cloned_body = body.clone();
-            let cloned_sender = self.result_sender.clone();
-            let formatted_name = task.formatted_name();
-
-            // TODO: cloning task is Bad, probably!
-            let mut cloned_task = task.clone();
-            
-            thread::spawn(move || {
-                let result = cloned_task.evaluate(&cloned_body, &cloned_globals);
-                cloned_sender.send((cloned_task.id, formatted_name, result))
-            });
+        self.globals.deadline = self.deadline.map(|deadline| self.globals.clock.now() + deadline);
+
+        // Tasks are moved onto their threads, so that their channels close once they finish
+        self.tasks.sort_by_key(|(task, _)| Reverse(task.priority));
+        for (task, body) in self.tasks.drain(..) {
+            spawn_task(task, body, self.globals.clone(), None);
         }
     }
 
@@ -89,18 +443,33 @@ impl Runtime {
         let mut results = HashMap::new();
 
         // Wait for a number of results equal to the number of tasks
-        // TODO: what about panics?
-        for _ in 0..self.tasks.len() {
-            let (id, name, result) = self.result_receiver.recv().unwrap();
+        // (Tasks can start more tasks, but always do so before they finish themselves, so the
+        // count is up-to-date by the time the last result is received)
+        while results.len() < self.globals.started_task_count.load(Ordering::SeqCst) {
+            let (_id, name, result) = self.result_receiver.recv().unwrap();
 
             match result {
                 Ok(ref value) => println!("Task {name} terminated with tail value {value:?}"),
                 Err(ref e) => println!("Task {name} encountered an error: {e:?}")
             }
 
+            if let Some(callback) = &mut self.task_complete_callback {
+                callback(&name, &result);
+            }
+
+            let failed = result.is_err();
             results.insert(name.to_string(), result);
+
+            // Results from tasks which were cancelled aren't interesting, so don't wait for them
+            if self.fail_fast && failed {
+                self.globals.cancelled.store(true, Ordering::SeqCst);
+                self.failed_task = Some(name);
+                break;
+            }
         }
 
+        self.warnings.extend(self.warning_receiver.try_iter());
+
         results
     }
 
@@ -114,21 +483,63 @@ impl Runtime {
             // Create channel to send to all others
             // TODO: tasks can't send to themselves - is this desirable?
             for (other, _) in left.iter_mut().chain(right.iter_mut()) {
-                let (sender, receiver) = crossbeam_channel::bounded(0);
+                let (sender, receiver) = crossbeam_channel::bounded(other.buffer_size);
                 other.receivers.insert(subject.id, receiver);
                 subject.senders.insert(other.id, sender);
             }
         }
     }
 
-    fn take_task_id(&mut self) -> TaskID {
-        let result = self.next_task_id;
-        self.next_task_id.0 += 1;
-        result
+    fn check_name_available(&self, name: &str) -> Result<(), InterpreterError> {
+        if self.globals.task_values_by_name.contains_key(name) {
+            Err(InterpreterError::new(format!("`{name}` is defined more than once")))
+        } else {
+            Ok(())
+        }
     }
+
+}
+
+/// Runs a task on a new thread, which reports its result to `Runtime::join` once it finishes - and
+/// also to `parent_result_sender`, for a task started by another which may `await` it.
+pub(crate) fn spawn_task(mut task: TaskState, body: Arc<TaskBody>, globals: Globals, parent_result_sender: Option<Sender<Result<Value, InterpreterError>>>) {
+    globals.started_task_count.fetch_add(1, Ordering::SeqCst);
+
+    thread::spawn(move || {
+        task.emit_event(&globals, TaskEventKind::Started);
+
+        let formatted_name = task.formatted_name();
+
+        // A bug in the interpreter shouldn't leave `join` waiting forever for this task's result,
+        // so turn a panic into an error
+        let result = panic::catch_unwind(AssertUnwindSafe(|| if globals.bytecode {
+            task.run_bytecode(body.bytecode(), &globals)
+        } else {
+            task.evaluate(&body.node, &globals)
+        }));
+        let result = result.unwrap_or_else(|payload| {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(InterpreterError::new(format!("task panicked: {message}")))
+        });
+        let result = result.and_then(|value| task.check_linear_channels(&globals).map(|_| value));
+        task.notify_peers(&globals);
+        if let Some(barrier) = globals.barriers.get(&task.name) {
+            barrier.finish();
+        }
+
+        task.emit_event(&globals, TaskEventKind::Completed(result.clone()));
+
+        // The parent may have finished without awaiting, in which case nobody wants the result
+        if let Some(parent_result_sender) = parent_result_sender {
+            let _ = parent_result_sender.send(result.clone());
+        }
+        globals.result_sender.send((task.id, formatted_name, result))
+    });
 }
 
-fn partition_slice_mut<'s, T>(slice: &'s mut [T], index: usize) -> (&'s mut [T], &'s mut T, &'s mut [T]) {
+fn partition_slice_mut<T>(slice: &mut [T], index: usize) -> (&mut [T], &mut T, &mut [T]) {
     let (left, rest) = slice.split_at_mut(index);
     let (middle, right) = rest.split_at_mut(1);
     (left, middle.first_mut().unwrap(), right)
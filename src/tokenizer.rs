@@ -1,6 +1,11 @@
+use std::{collections::VecDeque, io::{self, BufRead}};
+
 pub struct Tokenizer<'s> {
-    input: &'s [char],
-    index: usize,
+    input: CharStream<'s>,
+
+    // Where `input` is up to, for reporting errors - both start from 1
+    line: usize,
+    column: usize,
 
     indent_level: usize,
     indent_size: usize,
@@ -10,6 +15,69 @@ pub struct Tokenizer<'s> {
     pub errors: Vec<TokenizerError>,
 }
 
+/// The characters being tokenized. Only the next few are held at once, so that a large input doesn't
+/// need to be copied into memory in full.
+struct CharStream<'s> {
+    chars: Box<dyn Iterator<Item = io::Result<char>> + 's>,
+    lookahead: VecDeque<char>,
+    error: Option<io::Error>,
+}
+
+impl<'s> CharStream<'s> {
+    // The tokenizer never looks further ahead than this
+    const LOOKAHEAD: usize = 3;
+
+    fn new(chars: impl Iterator<Item = io::Result<char>> + 's) -> Self {
+        let mut stream = Self { chars: Box::new(chars), lookahead: VecDeque::new(), error: None };
+        stream.fill();
+        stream
+    }
+
+    fn fill(&mut self) {
+        while self.lookahead.len() < Self::LOOKAHEAD && self.error.is_none() {
+            match self.chars.next() {
+                Some(Ok(c)) => self.lookahead.push_back(c),
+                Some(Err(e)) => self.error = Some(e),
+                None => break,
+            }
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.lookahead.get(offset).copied()
+    }
+
+    fn advance(&mut self) {
+        self.lookahead.pop_front();
+        self.fill();
+    }
+}
+
+/// The characters of a reader, read one line at a time.
+struct ReaderChars<R> {
+    reader: R,
+    line: std::vec::IntoIter<char>,
+}
+
+impl<R: BufRead> Iterator for ReaderChars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(c) = self.line.next() {
+                return Some(Ok(c))
+            }
+
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line = line.chars().collect::<Vec<_>>().into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum IndentFormat {
     Spaces,
@@ -27,9 +95,11 @@ impl Token {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     IntegerLiteral(i64),
+    FloatLiteral(f64),
+    StringLiteral(String),
     Identifier(String),
 
     Add,
@@ -38,13 +108,22 @@ pub enum TokenKind {
     Divide,
 
     Equals,
+    NotEquals,
     GreaterThan,
+    GreaterThanOrEqual,
     LessThan,
+    LessThanOrEqual,
+
+    LogicalAnd,
+    LogicalOr,
 
     Range,
 
     SendArrow,
+    TrySendArrow,
+    FatArrow,
     ReceiveArrow,
+    TryReceiveArrow,
     QuestionMark,
 
     LeftParen,
@@ -53,17 +132,40 @@ pub enum TokenKind {
     LeftBrace,
     RightBrace,
     Comma,
+    Colon,
+    Semicolon,
 
     Assign,
 
     KwTask,
+    KwSpawn,
+    KwDebug,
+    KwConst,
     KwIf,
     KwWhile,
     KwLoop,
+    KwFor,
     KwTrue,
     KwFalse,
     KwNull,
     KwExit,
+    KwOr,
+    KwIn,
+    KwIsNull,
+    KwNot,
+    KwReady,
+    KwAwait,
+    KwPending,
+    KwBreak,
+    KwContinue,
+    KwYield,
+    KwPass,
+    KwBarrier,
+    KwFlush,
+    KwThen,
+    KwElse,
+    KwTry,
+    KwCatch,
 
     Indent,
     Dedent,
@@ -81,13 +183,35 @@ impl TokenizerError {
     fn new(s: impl Into<String>) -> Self {
         Self { message: s.into() }
     }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl<'s> Tokenizer<'s> {
     pub fn new(input: &'s [char]) -> Self {
+        Self::from_chars(input.iter().copied())
+    }
+
+    /// Tokenizes characters as they're produced, like `str::chars`, rather than needing them all
+    /// collected up front.
+    pub fn from_chars(input: impl Iterator<Item = char> + 's) -> Self {
+        Self::from_stream(CharStream::new(input.map(Ok)))
+    }
+
+    /// Tokenizes a reader one line at a time, so that only the tokens (and not the whole text) need
+    /// to be held in memory. If reading fails, tokenizing stops there with an error.
+    pub fn from_reader(input: impl BufRead + 's) -> Self {
+        Self::from_stream(CharStream::new(ReaderChars { reader: input, line: vec![].into_iter() }))
+    }
+
+    fn from_stream(input: CharStream<'s>) -> Self {
         Self {
             input,
-            index: 0,
+
+            line: 1,
+            column: 1,
 
             indent_level: 0,
             indent_size: 0,
@@ -98,6 +222,19 @@ impl<'s> Tokenizer<'s> {
         }
     }
 
+    fn follows_operand(&self) -> bool {
+        // A `-` after something which could be the left side of a subtraction is a subtraction,
+        // like `x -1`, rather than the start of a negative literal
+        matches!(
+            self.tokens.last().map(|t| &t.kind),
+            Some(
+                TokenKind::IntegerLiteral(_) | TokenKind::FloatLiteral(_) | TokenKind::StringLiteral(_)
+                | TokenKind::Identifier(_) | TokenKind::RightParen | TokenKind::RightBrace
+                | TokenKind::KwTrue | TokenKind::KwFalse | TokenKind::KwNull
+            )
+        )
+    }
+
     pub fn tokenize(&mut self) {
         while !self.is_at_end() {
             if self.this() == '#' {
@@ -146,12 +283,26 @@ impl<'s> Tokenizer<'s> {
             } else if self.this() == '<' && self.next() == '-' {
                 self.advance();
                 self.advance();
-                self.tokens.push(Token::new(TokenKind::ReceiveArrow));
+
+                // `<-?` is a non-blocking receive
+                if self.this() == '?' {
+                    self.advance();
+                    self.tokens.push(Token::new(TokenKind::TryReceiveArrow));
+                } else {
+                    self.tokens.push(Token::new(TokenKind::ReceiveArrow));
+                }
             } else if self.this() == '-' && self.next() == '>' {
                 self.advance();
                 self.advance();
-                self.tokens.push(Token::new(TokenKind::SendArrow));
-            } else if self.this().is_ascii_digit() || self.this() == '-' {
+
+                // `->?` is a non-blocking send
+                if self.this() == '?' {
+                    self.advance();
+                    self.tokens.push(Token::new(TokenKind::TrySendArrow));
+                } else {
+                    self.tokens.push(Token::new(TokenKind::SendArrow));
+                }
+            } else if self.this().is_ascii_digit() || (self.this() == '-' && self.next().is_ascii_digit() && !self.follows_operand()) {
                 // Parse the number into a character list
                 let mut buffer = vec![self.this()];
                 self.advance();
@@ -161,10 +312,47 @@ impl<'s> Tokenizer<'s> {
                     self.advance();
                 }
 
-                // Convert into an actual integer
+                // A decimal point followed by more digits makes this a float
+                // (Checking for the digit means ranges like `1..2` aren't mistaken for floats)
+                let is_float = self.this() == '.' && self.next().is_ascii_digit();
+                if is_float {
+                    buffer.push(self.this());
+                    self.advance();
+                    while self.this().is_ascii_digit() {
+                        buffer.push(self.this());
+                        self.advance();
+                    }
+                }
+
+                // Something like `1.2.3` isn't a number at all, so skip the whole thing
+                let mut malformed = false;
+                while is_float && self.this() == '.' && self.next().is_ascii_digit() {
+                    malformed = true;
+                    buffer.push(self.this());
+                    self.advance();
+                    while self.this().is_ascii_digit() {
+                        buffer.push(self.this());
+                        self.advance();
+                    }
+                }
+
+                // Convert into an actual number
                 let buffer_str: String = buffer.iter().collect();
-                let int = buffer_str.parse::<i64>().unwrap();
-                self.tokens.push(Token::new(TokenKind::IntegerLiteral(int)))
+                if malformed {
+                    self.errors.push(TokenizerError::new(format!("malformed number `{buffer_str}`")));
+                } else if is_float {
+                    self.tokens.push(Token::new(TokenKind::FloatLiteral(buffer_str.parse().unwrap())));
+                } else {
+                    match buffer_str.parse::<i64>() {
+                        Ok(int) => self.tokens.push(Token::new(TokenKind::IntegerLiteral(int))),
+                        Err(_) => self.errors.push(TokenizerError::new("integer literal out of range")),
+                    }
+                }
+            } else if self.this() == '"' {
+                match self.consume_string() {
+                    Ok(string) => self.tokens.push(Token::new(TokenKind::StringLiteral(string))),
+                    Err(e) => self.errors.push(e),
+                }
             } else if self.this().is_whitespace() {
                 self.advance(); // Skip whitespace
             } else {
@@ -183,13 +371,34 @@ impl<'s> Tokenizer<'s> {
                     '[' => self.tokens.push(Token::new(TokenKind::LeftBrace)),
                     ']' => self.tokens.push(Token::new(TokenKind::RightBrace)),
                     ',' => self.tokens.push(Token::new(TokenKind::Comma)),
+                    ':' => self.tokens.push(Token::new(TokenKind::Colon)),
+                    ';' => self.tokens.push(Token::new(TokenKind::Semicolon)),
 
                     '=' if self.next() == '=' => {
                         self.advance();
                         self.tokens.push(Token::new(TokenKind::Equals))
                     },
+                    '=' if self.next() == '>' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::FatArrow))
+                    },
                     '=' => self.tokens.push(Token::new(TokenKind::Assign)),
+                    '!' if self.next() == '=' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::NotEquals))
+                    },
+
+                    // `<-` and `->` were already handled above, so they take priority - `a <-1` is
+                    // a receive, and `a <= -1` is a comparison
+                    '>' if self.next() == '=' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::GreaterThanOrEqual))
+                    },
                     '>' => self.tokens.push(Token::new(TokenKind::GreaterThan)),
+                    '<' if self.next() == '=' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::LessThanOrEqual))
+                    },
                     '<' => self.tokens.push(Token::new(TokenKind::LessThan)),
 
                     '.' if self.next() == '.' => {
@@ -197,41 +406,47 @@ impl<'s> Tokenizer<'s> {
                         self.tokens.push(Token::new(TokenKind::Range))
                     },
 
+                    '&' if self.next() == '&' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::LogicalAnd))
+                    },
+                    '|' if self.next() == '|' => {
+                        self.advance();
+                        self.tokens.push(Token::new(TokenKind::LogicalOr))
+                    },
+
                     _ => self.push_unexpected_error(),
                 }
                 self.advance();
             }
         }
 
+        if let Some(e) = self.input.error.take() {
+            self.errors.push(TokenizerError::new(format!("could not read input: {e}")));
+        }
         self.tokens.push(Token::new(TokenKind::EndOfFile))
     }
 
     fn this(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.input[self.index]
-        }
+        self.input.peek(0).unwrap_or('\0')
     }
 
     fn next(&self) -> char {
-        if self.next_is_at_end() {
-            '\0'
-        } else {
-            self.input[self.index + 1]
-        }
+        self.input.peek(1).unwrap_or('\0')
     }
 
     fn is_at_end(&self) -> bool {
-        self.index >= self.input.len()
-    }
-
-    fn next_is_at_end(&self) -> bool {
-        self.index + 1 >= self.input.len()
+        self.input.peek(0).is_none()
     }
 
     fn advance(&mut self) {
-        self.index += 1;
+        if self.this() == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        self.input.advance();
     }
 
     fn try_get_identifier(&mut self) -> Option<String> {
@@ -254,17 +469,124 @@ impl<'s> Tokenizer<'s> {
     fn try_convert_to_keyword(s: &str) -> Option<TokenKind> {
         match s {
             "task" => Some(TokenKind::KwTask),
+            "spawn" => Some(TokenKind::KwSpawn),
+            "debug" => Some(TokenKind::KwDebug),
+            "const" => Some(TokenKind::KwConst),
             "true" => Some(TokenKind::KwTrue),
             "false" => Some(TokenKind::KwFalse),
             "null" => Some(TokenKind::KwNull),
             "if" => Some(TokenKind::KwIf),
             "while" => Some(TokenKind::KwWhile),
             "loop" => Some(TokenKind::KwLoop),
+            "for" => Some(TokenKind::KwFor),
             "exit" => Some(TokenKind::KwExit),
+            "or" => Some(TokenKind::KwOr),
+            "in" => Some(TokenKind::KwIn),
+            "isnull" => Some(TokenKind::KwIsNull),
+            "not" => Some(TokenKind::KwNot),
+            "ready" => Some(TokenKind::KwReady),
+            "await" => Some(TokenKind::KwAwait),
+            "pending" => Some(TokenKind::KwPending),
+            "break" => Some(TokenKind::KwBreak),
+            "continue" => Some(TokenKind::KwContinue),
+            "yield" => Some(TokenKind::KwYield),
+            "pass" => Some(TokenKind::KwPass),
+            "barrier" => Some(TokenKind::KwBarrier),
+            "flush" => Some(TokenKind::KwFlush),
+            "then" => Some(TokenKind::KwThen),
+            "else" => Some(TokenKind::KwElse),
+            "try" => Some(TokenKind::KwTry),
+            "catch" => Some(TokenKind::KwCatch),
             _ => None,
         }
     }
 
+    fn consume_string(&mut self) -> Result<String, TokenizerError> {
+        // Check for a multi-line string, opened with triple quotes
+        if self.next() == '"' && self.input.peek(2) == Some('"') {
+            return self.consume_multiline_string();
+        }
+
+        let (line, column) = (self.line, self.column);
+        self.advance(); // skip opening quote
+
+        // Keep going after a bad escape, so that the rest of the string isn't tokenized as code
+        let mut buffer = String::new();
+        let mut error = None;
+        loop {
+            match self.this() {
+                '"' => break,
+                '\n' | '\0' => return Err(TokenizerError::new(format!("unterminated string (line {line}, column {column})"))),
+                '\\' => {
+                    self.advance();
+                    match self.this() {
+                        'n' => buffer.push('\n'),
+                        't' => buffer.push('\t'),
+                        '"' => buffer.push('"'),
+                        '\\' => buffer.push('\\'),
+                        '\n' | '\0' => continue,
+                        c => {
+                            error.get_or_insert(TokenizerError::new(format!("unknown escape sequence `\\{c}` (line {}, column {})", self.line, self.column - 1)));
+                        }
+                    }
+                }
+                c => buffer.push(c),
+            }
+            self.advance();
+        }
+        self.advance(); // skip closing quote
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(buffer),
+        }
+    }
+
+    fn consume_multiline_string(&mut self) -> Result<String, TokenizerError> {
+        // Skip opening quotes
+        let (line, column) = (self.line, self.column);
+        for _ in 0..3 {
+            self.advance();
+        }
+
+        // Gather everything up to the closing quotes verbatim - newlines inside the string don't
+        // affect indentation
+        let mut buffer = String::new();
+        loop {
+            if self.is_at_end() {
+                return Err(TokenizerError::new(format!("unterminated multi-line string (line {line}, column {column})")));
+            }
+            if self.this() == '"' && self.next() == '"' && self.input.peek(2) == Some('"') {
+                break;
+            }
+            buffer.push(self.this());
+            self.advance();
+        }
+        for _ in 0..3 {
+            self.advance();
+        }
+
+        // Tidy up the contents:
+        //   - A newline directly after the opening quotes is removed
+        //   - If the closing quotes are on their own line, that line is removed
+        //   - Indentation common to every non-blank line is removed
+        let buffer = buffer.strip_prefix('\n').unwrap_or(&buffer);
+        let mut lines: Vec<_> = buffer.split('\n').collect();
+        if lines.len() > 1 && lines.last().unwrap().chars().all(|c| c == ' ' || c == '\t') {
+            lines.pop();
+        }
+        let common_indent = lines.iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+            .min()
+            .unwrap_or(0);
+
+        Ok(lines.iter()
+            .map(|line| line.get(common_indent..).unwrap_or(""))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
     fn consume_all_indentation(&mut self) -> Result<usize, TokenizerError> {
         // If lines are blank, ignore them and move onto the next one
         while self.this() == '\n' {
@@ -317,8 +639,10 @@ impl<'s> Tokenizer<'s> {
                 return Ok(indent_level)
             }
 
-            if this_indent.unwrap() != self.indent_format {
-                return Err(TokenizerError::new("indentation mismatch"))
+            // Every character of one line's indentation should be the same
+            if this_indent.unwrap() != given_format {
+                return Err(TokenizerError::new(format!(
+                    "mixed tabs and spaces in indentation (column {})", current_indent_size + 1)))
             }
             
             current_indent_size += 1;
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::interpreter::TaskID;
+
+/// Every decision made by each task which depended on the timing of other tasks, such as which
+/// channel a binding receive took a value from. Replaying a trace makes every task make the same
+/// decisions again, so a program runs exactly as it did when the trace was recorded.
+///
+/// A trace can be converted to and from a string to save it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub events: HashMap<TaskID, Vec<TraceEvent>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A receive got a value from a task, or from `$in` if the task is `None`.
+    Received(Option<TaskID>),
+    /// A non-blocking receive found nothing waiting.
+    Empty,
+    /// A receive found that its channel was closed.
+    Closed,
+    /// The result of a `ready` check.
+    Ready(bool),
+    /// The result of a `pending` check.
+    Pending(usize),
+    /// Whether a non-blocking send succeeded.
+    TrySent(bool),
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ids: Vec<_> = self.events.keys().collect();
+        ids.sort_by_key(|id| id.0);
+
+        for id in ids {
+            for event in &self.events[id] {
+                writeln!(f, "{id} {event}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceEvent::Received(Some(id)) => write!(f, "received {id}"),
+            TraceEvent::Received(None) => write!(f, "received $in"),
+            TraceEvent::Empty => write!(f, "empty"),
+            TraceEvent::Closed => write!(f, "closed"),
+            TraceEvent::Ready(ready) => write!(f, "ready {ready}"),
+            TraceEvent::Pending(pending) => write!(f, "pending {pending}"),
+            TraceEvent::TrySent(sent) => write!(f, "trysent {sent}"),
+        }
+    }
+}
+
+impl FromStr for Trace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut trace = Trace::default();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || format!("invalid trace line `{line}`");
+
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let id = TaskID(parts[0].parse().map_err(|_| invalid())?);
+            let event = match parts[1..] {
+                ["received", "$in"] => TraceEvent::Received(None),
+                ["received", from] => TraceEvent::Received(Some(TaskID(from.parse().map_err(|_| invalid())?))),
+                ["empty"] => TraceEvent::Empty,
+                ["closed"] => TraceEvent::Closed,
+                ["ready", ready] => TraceEvent::Ready(ready.parse().map_err(|_| invalid())?),
+                ["pending", pending] => TraceEvent::Pending(pending.parse().map_err(|_| invalid())?),
+                ["trysent", sent] => TraceEvent::TrySent(sent.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            };
+
+            trace.events.entry(id).or_default().push(event);
+        }
+        Ok(trace)
+    }
+}
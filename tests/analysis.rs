@@ -0,0 +1,201 @@
+use conker::{check, program_hash, task_summary};
+use indoc::indoc;
+
+#[test]
+fn test_check_valid() {
+    let diagnostics = check(indoc!{"
+        task Adder
+            a <- ?c
+            b <- c
+            a + b -> c
+
+        task Main
+            5 -> Adder
+            4 -> Adder
+            result <- Adder
+            result -> $out
+    "});
+    assert!(!diagnostics.has_errors());
+    assert!(diagnostics.warnings.is_empty());
+}
+
+#[test]
+fn test_check_duplicate_and_unused() {
+    let diagnostics = check(indoc!{"
+        task A
+            x = 1
+            _y = 2
+            3 -> B
+
+        task A
+            null
+
+        task B
+            z <- A
+            z -> $out
+    "});
+    assert_eq!(diagnostics.errors, vec!["task `A` is defined more than once"]);
+    assert_eq!(diagnostics.warnings, vec!["variable `x` in task `A` is never used"]);
+}
+
+#[test]
+fn test_check_receive_timeout_is_used() {
+    let diagnostics = check(indoc!{"
+        task A
+            t = 100
+            x <- B timeout t or null
+            x -> $out
+
+        task B
+            1 -> A
+    "});
+    assert_eq!(diagnostics.warnings, Vec::<String>::new());
+}
+
+#[test]
+fn test_check_unknown_channel() {
+    let diagnostics = check(indoc!{"
+        task A
+            1 -> Nowhere
+            x <- ?c
+            x -> c
+    "});
+    assert_eq!(diagnostics.errors, vec!["task `A` uses unknown channel `Nowhere`"]);
+}
+
+#[test]
+fn test_check_syntax_errors() {
+    let diagnostics = check(indoc!{"
+        task A
+            1 -> @
+    "});
+    assert!(diagnostics.has_errors());
+}
+
+#[test]
+fn test_check_shadowed_task() {
+    let diagnostics = check(indoc!{"
+        task Worker
+            x <- Main
+            x -> $out
+
+        task Main
+            Worker = 5
+            Worker -> Worker
+    "});
+    assert!(!diagnostics.has_errors());
+    assert_eq!(diagnostics.warnings, vec!["variable `Worker` in task `Main` shadows the task of the same name"]);
+}
+
+#[test]
+fn test_check_missing_task_indent() {
+    // The second task is still parsed and analysed, despite the first being broken
+    let diagnostics = check(indoc!{"
+        task Foo
+        1 -> $out
+
+        task Bar
+            1 -> Baz
+    "});
+    assert_eq!(diagnostics.errors, vec![
+        "expected an indented body after task `Foo`",
+        "task `Bar` uses unknown channel `Baz`",
+    ]);
+}
+
+#[test]
+fn test_alternates() {
+    // Sending twice in a row, or twice round a loop, breaks a ping-pong protocol
+    let diagnostics = check(indoc!{"
+        task Server
+            loop
+                x <- Client
+                x -> Client
+
+        task Client alternates Server
+            1 -> Server
+            2 -> Server
+            _ <- Server
+
+        task Looper alternates Server
+            loop
+                1 -> Server
+    "});
+    assert!(!diagnostics.has_errors());
+    assert_eq!(diagnostics.warnings, vec![
+        "task `Client` can send to `Server` twice without receiving in between",
+        "task `Looper` can send to `Server` twice without receiving in between",
+    ]);
+
+    // Taking turns is fine, including when the turns happen in different branches
+    let diagnostics = check(indoc!{"
+        task Server alternates Client
+            loop
+                x <- Client
+                if x > 0
+                    x -> Client
+                if x < 1
+                    0 -> Client
+
+        task Client alternates Server
+            1 -> Server
+            _ <- Server
+    "});
+    assert!(diagnostics.warnings.is_empty());
+
+    assert_eq!(check("task A alternates B\n    null\n").errors, vec!["task `A` alternates with unknown task `B`"]);
+}
+
+#[test]
+fn test_task_summary() {
+    let summary = task_summary(indoc!{"
+        const SIZE = 3
+
+        task Worker[3]
+            $index -> Main
+
+        task Main
+            gather(Worker)
+    "});
+    assert_eq!(summary.unwrap(), vec![
+        ("Worker".to_string(), Some(3)),
+        ("Main".to_string(), None),
+    ]);
+
+    let errors = task_summary("task\n").unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_program_hash() {
+    let hash = program_hash(indoc!{"
+        task Main
+            x = [1, 2.5, \"a\"]
+            x -> $out
+    "}).unwrap();
+
+    // Formatting and comments don't matter...
+    assert_eq!(hash, program_hash(indoc!{"
+        # Prints an array
+        task Main
+            x   =   [ 1,2.5, \"a\" ]   # with a comment
+
+
+            x->$out
+    "}).unwrap());
+
+    // ...but anything else does
+    assert_ne!(hash, program_hash(indoc!{"
+        task Main
+            x = [1, 2.5, \"b\"]
+            x -> $out
+    "}).unwrap());
+    assert_ne!(program_hash("task Main\n    x <- A or 1\n").unwrap(), program_hash("task Main\n    x <- A\n").unwrap());
+
+    assert!(program_hash("task\n").is_err());
+}
+
+#[test]
+fn test_notify_unknown_task() {
+    assert_eq!(check("task A notify B\n    null\n").errors, vec!["task `A` notifies unknown task `B`"]);
+}
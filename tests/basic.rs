@@ -1,9 +1,9 @@
 use std::collections::HashMap;
 
-use conker::{interpreter::Value, run_code};
+use conker::{interpreter::{InterpreterErrorKind, Value}, check, run_code};
 use indoc::indoc;
 
-use crate::utils::{run_one_task, run_one_expression};
+use crate::utils::{run_one_task, run_one_expression, run_binary};
 
 mod utils;
 
@@ -13,6 +13,39 @@ fn test_arithmetic() {
         run_one_expression("12 + 3"),
         Ok(Value::Integer(15))
     );
+    assert_eq!(
+        run_one_expression("12 - 3"),
+        Ok(Value::Integer(9))
+    );
+    assert_eq!(
+        run_one_expression("12 - -3"),
+        Ok(Value::Integer(15))
+    );
+}
+
+#[test]
+fn test_negative_literals() {
+    assert_eq!(
+        run_one_expression("[-1, -2]"),
+        Ok(Value::Array(vec![Value::Integer(-1), Value::Integer(-2)]))
+    );
+    assert_eq!(
+        run_one_expression("[1, -2, 3]"),
+        Ok(Value::Array(vec![Value::Integer(1), Value::Integer(-2), Value::Integer(3)]))
+    );
+    assert_eq!(
+        run_one_expression("[3-1, 3 -1, -1.5]"),
+        Ok(Value::Array(vec![Value::Integer(2), Value::Integer(2), Value::Float(-1.5)]))
+    );
+    assert_eq!(run_one_expression("clamp(-1, 2, 5)"), Ok(Value::Integer(2)));
+    assert_eq!(run_one_expression("clamp(-10, -5, 5)"), Ok(Value::Integer(-5)));
+
+    // Anything else can be negated too
+    assert_eq!(run_one_task("task X\n    x = 4\n    [x-1, -x, -(x * 2)]\n"), Ok(Value::Array(vec![
+        Value::Integer(3),
+        Value::Integer(-4),
+        Value::Integer(-8),
+    ])));
 }
 
 #[test]
@@ -44,6 +77,33 @@ fn test_comparisons() {
         run_one_expression("4 < 5"),
         Ok(Value::Boolean(true))
     );
+
+    assert_eq!(
+        run_one_expression("4 <= 4"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("5 <= 4"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("4 >= 4.5"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("0 >= -1"),
+        Ok(Value::Boolean(true))
+    );
+
+    // `<=` isn't confused with a receive
+    assert_eq!(
+        run_one_task(indoc!{"
+            task Main
+                a = 0
+                a <= -1
+        "}),
+        Ok(Value::Boolean(false))
+    );
 }
 
 #[test]
@@ -92,6 +152,86 @@ fn test_array() {
             Value::Integer(40),
         ]))
     );
+
+    // Open-ended slices
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = [ 10, 20, 30, 40, 50 ]
+                [ x[2..], x[..3], x[..], x[-2..] ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(30), Value::Integer(40), Value::Integer(50)]),
+            Value::Array(vec![Value::Integer(10), Value::Integer(20), Value::Integer(30)]),
+            Value::Array(vec![
+                Value::Integer(10),
+                Value::Integer(20),
+                Value::Integer(30),
+                Value::Integer(40),
+                Value::Integer(50),
+            ]),
+            Value::Array(vec![Value::Integer(40), Value::Integer(50)]),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("(10 .. 20)[5..]"),
+        Ok(Value::Range {
+            begin: Box::new(Value::Integer(15)),
+            end: Box::new(Value::Integer(20)),
+        })
+    );
+}
+
+#[test]
+fn test_range_index() {
+    assert_eq!(
+        run_one_expression("(0 .. 10)[3]"),
+        Ok(Value::Integer(3))
+    );
+    assert_eq!(
+        run_one_expression("(5 .. 10)[-1]"),
+        Ok(Value::Integer(9))
+    );
+    assert_eq!(
+        run_one_expression("(10 .. 0)[2]"),
+        Ok(Value::Integer(8))
+    );
+    assert!(run_one_expression("(0 .. 10)[10]").is_err());
+    assert!(run_one_expression("(0 .. 10)[-11]").is_err());
+    assert!(run_one_expression("(0 .. 0)[0]").is_err());
+
+    // Slice indexing gives another range
+    assert_eq!(
+        run_one_expression("(10 .. 20)[2 .. 5]"),
+        Ok(Value::Range {
+            begin: Box::new(Value::Integer(12)),
+            end: Box::new(Value::Integer(15)),
+        })
+    );
+    assert!(run_one_expression("(10 .. 20)[5 .. 11]").is_err());
+}
+
+#[test]
+fn test_conditional_expression() {
+    assert_eq!(
+        run_one_expression("if 1 < 2 then \"yes\" else \"no\""),
+        Ok(Value::String("yes".to_string()))
+    );
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = 5
+                y = if x > 10 then x else 10
+                if y == 10 then [ y ] else [ ]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(10)]))
+    );
+
+    // The other branch isn't evaluated
+    assert_eq!(
+        run_one_expression("if true then 1 else head([ ])"),
+        Ok(Value::Integer(1))
+    );
 }
 
 #[test]
@@ -137,3 +277,636 @@ fn test_precedence() {
         ]))
     );
 }
+
+#[test]
+fn test_equality() {
+    assert_eq!(
+        run_one_expression("[ 1, [ 2, 3 ] ] == [ 1, [ 2, 3 ] ]"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("[ 1, 2 ] == [ 1, 3 ]"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("null == null"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("1 == true"),
+        Ok(Value::Boolean(false))
+    );
+
+    assert_eq!(
+        run_one_expression("[ 1, 2 ] != [ 1, 3 ]"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("\"a\" != \"a\""),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("null != 0"),
+        Ok(Value::Boolean(true))
+    );
+}
+
+#[test]
+fn test_task_reference_equality() {
+    assert_eq!(
+        run_code(indoc!{"
+            task Peer
+                null -> Main
+
+            task Other
+                null
+
+            task Main
+                x <- ?c
+                [ c == Peer, c == Other, Main == Main ]
+        "}),
+        Some(HashMap::from([
+            ("Peer".to_string(), Ok(Value::Null)),
+            ("Other".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Boolean(true),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_receive_default() {
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                1 -> Consumer
+
+            task Consumer
+                a <- Producer or 0
+                b <- Producer or 0
+                [ a, b ]
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Null)),
+            ("Consumer".to_string(), Ok(Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(0),
+            ]))),
+        ]))
+    );
+
+    // Binding receives fall back to the default once every peer has finished
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                1 -> Consumer
+
+            task Consumer
+                a <- ?c or 0
+                b <- ?c or 0
+                [ a, b, c ]
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Null)),
+            ("Consumer".to_string(), Ok(Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(0),
+                Value::Null,
+            ]))),
+        ]))
+    );
+
+    // Without a default, receiving from a finished task is an error
+    let results = run_code(indoc!{"
+        task Producer
+            null
+
+        task Consumer
+            a <- Producer
+    "}).unwrap();
+    assert!(results["Consumer"].is_err());
+}
+
+#[test]
+fn test_try_receive() {
+    // Nothing to receive
+    assert_eq!(
+        run_code(indoc!{"
+            task Silent
+                _ <- Poller or null
+
+            task Poller
+                x <-? Silent
+        "}),
+        Some(HashMap::from([
+            ("Silent".to_string(), Ok(Value::Null)),
+            ("Poller".to_string(), Ok(Value::Boolean(false))),
+        ]))
+    );
+
+    // Poll until something arrives
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                1 -> Poller
+                2 -> Poller
+
+            task Poller
+                x = null
+                while x == null
+                    x <-? Producer
+                y = null
+                while y == null
+                    y <-? ?c
+                [ x, y ]
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Null)),
+            ("Poller".to_string(), Ok(Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_isnull() {
+    assert_eq!(
+        run_one_expression("isnull null"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("isnull 0"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("isnull [ ]"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = [ 1, null ]
+                [ isnull x[0], isnull x[1] ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Boolean(false),
+            Value::Boolean(true),
+        ]))
+    );
+}
+
+#[test]
+fn test_ready() {
+    // `Signal` only tells `Main` to check again once `Producer` has sent into the buffer
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                _ <- Main
+                5 -> Main
+                null -> Signal
+
+            task Signal
+                _ <- Producer
+                null -> Main
+
+            task Main buffer 1
+                before = ready Producer
+                null -> Producer
+                _ <- Signal
+                after = ready Producer
+                x <- Producer
+                [ before, after, x, ready Producer ]
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Null)),
+            ("Signal".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Integer(5),
+                Value::Boolean(false),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_pending() {
+    // As with `ready`, `Signal` lets `Main` know when `Producer` has filled the buffer
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                1 -> Main
+                2 -> Main
+                3 -> Main
+                null -> Signal
+
+            task Signal
+                _ <- Producer
+                null -> Main
+
+            task Main buffer 3
+                _ <- Signal
+                before = pending Producer
+                x <- Producer
+                [ before, pending Producer, pending [ Producer, Signal ], pending $in ]
+        "}).unwrap()["Main"],
+        Ok(Value::Array(vec![
+            Value::Integer(3),
+            Value::Integer(2),
+            Value::Integer(2),
+            Value::Integer(0),
+        ]))
+    );
+
+    assert!(run_one_expression("pending 1").is_err());
+}
+
+#[test]
+fn test_in() {
+    // Large collections, where the answer is near the start or end
+    assert_eq!(run_one_expression("0 in fill(0, 1000000)"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("1 in fill(0, 1000000)"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("999999999 in 0..1000000000"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("1000000000 in 0..1000000000"), Ok(Value::Boolean(false)));
+
+    assert_eq!(run_one_expression("2 in [1, 2, 3]"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("[1] in [[1], [2]]"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("3 in 5..3"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("5 in 5..3"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("1000 in 3.."), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("1.5 in 0..3"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("\"ell\" in \"hello\""), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("1 + 1 in [2]"), Ok(Value::Boolean(true)));
+
+    assert!(run_one_expression("1 in 1").is_err());
+}
+
+#[test]
+fn test_try_send() {
+    // `Gate` holds `Main` back from receiving until both sends have been attempted
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                a = 1 ->? Main
+                b = 2 ->? Main
+                null -> Gate
+                [ a, b ]
+
+            task Gate
+                _ <- Producer
+                null -> Main
+
+            task Main buffer 1
+                _ <- Gate
+                x <- Producer
+                x
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Array(vec![
+                Value::Boolean(true),
+                Value::Boolean(false),
+            ]))),
+            ("Gate".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Integer(1))),
+        ]))
+    );
+}
+
+#[test]
+fn test_constants() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            const SIZE = 5
+            const LAST = SIZE - 1
+
+            task X
+                total = 0
+                i = 0
+                while i < SIZE
+                    total = total + i
+                    i = i + 1
+                [ total, LAST ]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(10), Value::Integer(4)]))
+    );
+
+    // Constants can't depend on channels
+    assert_eq!(
+        run_code(indoc!{"
+            const X = ready $in
+
+            task Y
+                X
+        "}),
+        None
+    );
+    assert_eq!(
+        check(indoc!{"
+            const X = gather(W)
+
+            task W[2]
+                $index -> Y
+
+            task Y
+                X
+        "}).errors,
+        vec!["value of constant `X` must not use channels"]
+    );
+}
+
+#[test]
+fn test_if_else() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task Main
+                a = null
+                if 1 > 2
+                    a = \"yes\"
+                else
+                    a = \"no\"
+                b = null
+                if 2 > 1
+                    b = \"yes\"
+                else
+                    if true
+                        b = \"nested\"
+                c = \"unchanged\"
+                if false
+                    c = \"changed\"
+                [a, b, c]
+        "}),
+        Ok(Value::Array(vec![
+            Value::String("no".to_string()),
+            Value::String("yes".to_string()),
+            Value::String("unchanged".to_string()),
+        ]))
+    );
+}
+
+#[test]
+fn test_guarded_if() {
+    // Nothing is pending for the first `if`, but `Gate` makes sure something is for the second
+    assert_eq!(
+        run_code(indoc!{"
+            task Producer
+                _ <- Main
+                5 -> Main
+                null -> Gate
+
+            task Gate
+                _ <- Producer
+                null -> Main
+
+            task Main buffer 1
+                before = null
+                if x <- ?c
+                    before = x
+                null -> Producer
+                _ <- Gate
+                after = null
+                if y <- Producer
+                    after = y
+                [ before, after ]
+        "}),
+        Some(HashMap::from([
+            ("Producer".to_string(), Ok(Value::Null)),
+            ("Gate".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Null,
+                Value::Integer(5),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_tagged() {
+    assert_eq!(
+        run_one_expression("Msg(\"ping\", 1 + 1)"),
+        Ok(Value::Tagged {
+            tag: "Msg".to_string(),
+            fields: vec![Value::String("ping".to_string()), Value::Integer(2)],
+        })
+    );
+    assert_eq!(
+        run_one_expression("Msg(\"ping\", 2)[1]"),
+        Ok(Value::Integer(2))
+    );
+    assert_eq!(
+        run_one_expression("tag(Stop())"),
+        Ok(Value::String("Stop".to_string()))
+    );
+
+    // Tags and fields both have to match for tagged values to be equal
+    assert_eq!(run_one_expression("Pair(1, 2) == Pair(1, 2)"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("Pair(1, 2) == Pair(1, 3)"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("Pair(1, 2) == Other(1, 2)"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("Pair(1, 2) == [ 1, 2 ]"), Ok(Value::Boolean(false)));
+
+    assert!(run_one_expression("tag([ 1 ])").is_err());
+}
+
+#[test]
+fn test_receive_type_assertion() {
+    let results = run_code(indoc!{"
+        task Sender
+            1 -> Receiver
+            \"two\" -> Receiver
+
+        task Receiver
+            a <- Sender as integer
+            b <- Sender as integer
+            a
+    "}).unwrap();
+    let error = results["Receiver"].as_ref().unwrap_err();
+    assert_eq!(error.kind(), InterpreterErrorKind::TypeMismatch);
+    assert_eq!(error.message(), "expected to receive a value of type integer, but received two");
+
+    assert_eq!(
+        run_code(indoc!{"
+            task Sender
+                [ 1 ] -> Receiver
+                null -> Receiver
+                Receiver -> Receiver
+
+            task Receiver
+                a <- Sender as array
+                b <- Sender as null
+                c <- ?s as task
+                [ a, b, c == Receiver ]
+        "}).unwrap()["Receiver"],
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(1)]),
+            Value::Null,
+            Value::Boolean(true),
+        ]))
+    );
+
+    // Defaults aren't checked
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x <- $in as integer or \"none\"
+                x
+        "}),
+        Ok(Value::String("none".to_string()))
+    );
+
+    assert!(run_code("task X\n    x <- $in as number\n").is_none());
+}
+
+#[test]
+fn test_debug() {
+    let output = run_binary("debug", indoc!{"
+        task Main
+            x = 3
+            y = debug x + 1
+            debug (y - 1) * 2
+            debug [y, \"a\"][-1]
+    "});
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().collect::<Vec<_>>(), vec![
+        "x + 1 = 4",
+        "(y - 1) * 2 = 6",
+        "[y, \"a\"][-1] = a",
+    ]);
+
+    // It evaluates to the value
+    assert_eq!(run_one_expression("debug 1 + 2"), Ok(Value::Integer(3)));
+}
+
+#[test]
+fn test_pass() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = 1
+                if x == 2
+                    pass
+                x
+        "}),
+        Ok(Value::Integer(1))
+    );
+
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                pass
+        "}),
+        Ok(Value::Null)
+    );
+}
+
+#[test]
+fn test_destructuring() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                [a, b, c] = [1, 2, 3]
+                [a, b, c]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]))
+    );
+
+    // A rest pattern takes whatever is left, even if that's nothing
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                [head, rest..] = [1, 2, 3]
+                [only, none..] = [4]
+                [head, rest, only, none]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+            Value::Integer(4),
+            Value::Array(vec![]),
+        ]))
+    );
+
+    // Patterns can be nested
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                [[a, b], c] = [[1, 2], 3]
+                a + b + c
+        "}),
+        Ok(Value::Integer(6))
+    );
+
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                [a, b] = [1, 2, 3]
+        "}).unwrap_err().message(),
+        "expected an array of length 2 to destructure, but found length 3"
+    );
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                [a, b, rest..] = [1]
+        "}).unwrap_err().message(),
+        "expected an array of at least length 2 to destructure, but found length 1"
+    );
+}
+
+#[test]
+fn test_logical_operators() {
+    assert_eq!(
+        run_one_expression("[true && true, true && false, false || true, false || false, 1 && \"a\", null || false]"),
+        Ok(Value::Array(vec![
+            Value::Boolean(true), Value::Boolean(false), Value::Boolean(true),
+            Value::Boolean(false), Value::Boolean(true), Value::Boolean(false),
+        ]))
+    );
+
+    // `&&` binds tighter than `||`, and both looser than comparisons
+    assert_eq!(run_one_expression("1 < 2 && 3 > 4 || 5 == 5"), Ok(Value::Boolean(true)));
+    assert_eq!(run_one_expression("true || false && false"), Ok(Value::Boolean(true)));
+
+    // The right side isn't evaluated if the left decides the result
+    assert_eq!(run_one_expression("false && 1 / 0"), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression("true || 1 / 0"), Ok(Value::Boolean(true)));
+    assert!(run_one_expression("true && 1 / 0").is_err());
+
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                i = 0
+                while i < 10 && i * i < 20
+                    i = i + 1
+                i
+        "}),
+        Ok(Value::Integer(5))
+    );
+}
+
+#[test]
+fn test_not() {
+    assert_eq!(
+        run_one_expression("[not true, not false, not null, not 0, not not 3, not 1 == 2]"),
+        Ok(Value::Array(vec![
+            Value::Boolean(false), Value::Boolean(true), Value::Boolean(true),
+            Value::Boolean(false), Value::Boolean(true), Value::Boolean(false),
+        ]))
+    );
+
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                done = false
+                i = 0
+                while not done
+                    i = i + 1
+                    done = i == 3
+                i
+        "}),
+        Ok(Value::Integer(3))
+    );
+}
@@ -0,0 +1,514 @@
+use conker::interpreter::Value;
+use indoc::indoc;
+
+use crate::utils::{run_one_expression, run_one_task};
+
+mod utils;
+
+#[test]
+fn test_head_tail_last() {
+    assert_eq!(
+        run_one_expression("head([ 1, 2, 3 ])"),
+        Ok(Value::Integer(1))
+    );
+    assert_eq!(
+        run_one_expression("tail([ 1, 2, 3 ])"),
+        Ok(Value::Array(vec![
+            Value::Integer(2),
+            Value::Integer(3),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("last([ 1, 2, 3 ])"),
+        Ok(Value::Integer(3))
+    );
+
+    // Empty arrays
+    assert!(run_one_expression("head([ ])").is_err());
+    assert!(run_one_expression("last([ ])").is_err());
+    assert_eq!(
+        run_one_expression("tail([ ])"),
+        Ok(Value::Array(vec![]))
+    );
+
+    // Strings
+    assert_eq!(
+        run_one_expression("head(\"héllo\")"),
+        Ok(Value::String("h".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("tail(\"héllo\")"),
+        Ok(Value::String("éllo".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("last(\"héllo\")"),
+        Ok(Value::String("o".to_string()))
+    );
+    assert!(run_one_expression("head(\"\")").is_err());
+
+    // Wrong argument types and counts
+    assert!(run_one_expression("head(1)").is_err());
+    assert!(run_one_expression("head([ 1 ], [ 2 ])").is_err());
+}
+
+#[test]
+fn test_sort() {
+    assert_eq!(
+        run_one_expression("sort([ 3, 1, 2 ])"),
+        Ok(Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("sort([ ])"),
+        Ok(Value::Array(vec![]))
+    );
+    assert_eq!(
+        run_one_expression("sort([ [ 2 ], [ 1, 5 ], [ 1 ] ])"),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(1)]),
+            Value::Array(vec![Value::Integer(1), Value::Integer(5)]),
+            Value::Array(vec![Value::Integer(2)]),
+        ]))
+    );
+
+    // Mixed types can't be ordered
+    assert!(run_one_expression("sort([ 1, true, 2 ])").is_err());
+}
+
+#[test]
+fn test_flatten() {
+    assert_eq!(
+        run_one_expression("flatten([ [ 1, 2 ], [ 3 ], [ ], [ 4, 5 ] ])"),
+        Ok(Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+            Value::Integer(4),
+            Value::Integer(5),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("flatten([ ])"),
+        Ok(Value::Array(vec![]))
+    );
+
+    // Only one level is flattened
+    assert_eq!(
+        run_one_expression("flatten([ [ [ 1 ] ] ])"),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(1)]),
+        ]))
+    );
+
+    assert!(run_one_expression("flatten([ [ 1 ], 2 ])").is_err());
+}
+
+#[test]
+fn test_fill() {
+    assert_eq!(
+        run_one_expression("fill(7, 5)"),
+        Ok(Value::Array(vec![Value::Integer(7); 5]))
+    );
+    assert_eq!(
+        run_one_expression("fill([ 1 ], 2)"),
+        Ok(Value::Array(vec![Value::Array(vec![Value::Integer(1)]); 2]))
+    );
+    assert_eq!(
+        run_one_expression("fill(7, 0)"),
+        Ok(Value::Array(vec![]))
+    );
+
+    assert!(run_one_expression("fill(7, -1)").is_err());
+    assert!(run_one_expression("fill(7, 1.5)").is_err());
+}
+
+#[test]
+fn test_unique() {
+    assert_eq!(
+        run_one_expression("unique([ 1, 2, 2, 3, 1 ])"),
+        Ok(Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("unique([ [ 1 ], \"a\", [ 1 ], \"a\", [ 1, 1 ] ])"),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(1)]),
+            Value::String("a".to_string()),
+            Value::Array(vec![Value::Integer(1), Value::Integer(1)]),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("unique([ 1.5, 1, [ 1.5 ], 1.5, 1, [ 1.5 ] ])"),
+        Ok(Value::Array(vec![
+            Value::Float(1.5),
+            Value::Integer(1),
+            Value::Array(vec![Value::Float(1.5)]),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("unique([ ])"),
+        Ok(Value::Array(vec![]))
+    );
+
+    assert!(run_one_expression("unique(1)").is_err());
+}
+
+#[test]
+fn test_reverse() {
+    assert_eq!(
+        run_one_expression("reverse([ 1, 2, 3 ])"),
+        Ok(Value::Array(vec![
+            Value::Integer(3),
+            Value::Integer(2),
+            Value::Integer(1),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression("reverse([ ])"),
+        Ok(Value::Array(vec![]))
+    );
+    assert_eq!(
+        run_one_expression("reverse(\"abc\")"),
+        Ok(Value::String("cba".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("reverse(\"\")"),
+        Ok(Value::String("".to_string()))
+    );
+
+    assert!(run_one_expression("reverse(1)").is_err());
+}
+
+#[test]
+fn test_any_all() {
+    assert_eq!(
+        run_one_expression("all([ 1, 2, 3 ], x => x > 0)"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("all([ 1, -2, 3 ], x => x > 0)"),
+        Ok(Value::Boolean(false))
+    );
+    assert_eq!(
+        run_one_expression("any([ 1, 0, 3 ], x => x == 0)"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("any([ 1, 2, 3 ], x => x == 0)"),
+        Ok(Value::Boolean(false))
+    );
+
+    // Empty arrays
+    assert_eq!(
+        run_one_expression("all([ ], x => x > 0)"),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression("any([ ], x => x > 0)"),
+        Ok(Value::Boolean(false))
+    );
+
+    // Evaluation stops as soon as the answer is known, so the later item is never compared
+    assert_eq!(
+        run_one_expression("any([ 1, true ], x => x > 0)"),
+        Ok(Value::Boolean(true))
+    );
+    assert!(run_one_expression("all([ 1, true ], x => x > 0)").is_err());
+
+    // The parameter doesn't leak out, or clobber an existing local
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = 5
+                all([ 1, 2 ], x => x > 0)
+                x
+        "}),
+        Ok(Value::Integer(5))
+    );
+
+    assert!(run_one_expression("all([ 1 ], 1)").is_err());
+}
+
+#[test]
+fn test_count() {
+    assert_eq!(
+        run_one_expression("count([ 1, 2, 1, 3, 1 ], 1)"),
+        Ok(Value::Integer(3))
+    );
+    assert_eq!(
+        run_one_expression("count([ [ 1 ], 1, [ 1 ] ], [ 1 ])"),
+        Ok(Value::Integer(2))
+    );
+    assert_eq!(
+        run_one_expression("count([ 1, -2, 3, -4, 5 ], e => e > 0)"),
+        Ok(Value::Integer(3))
+    );
+
+    // Empty arrays
+    assert_eq!(
+        run_one_expression("count([ ], 1)"),
+        Ok(Value::Integer(0))
+    );
+    assert_eq!(
+        run_one_expression("count([ ], e => e > 0)"),
+        Ok(Value::Integer(0))
+    );
+}
+
+#[test]
+fn test_clamp() {
+    assert_eq!(
+        run_one_expression("clamp(-5, 0, 10)"),
+        Ok(Value::Integer(0))
+    );
+    assert_eq!(
+        run_one_expression("clamp(5, 0, 10)"),
+        Ok(Value::Integer(5))
+    );
+    assert_eq!(
+        run_one_expression("clamp(15, 0, 10)"),
+        Ok(Value::Integer(10))
+    );
+    assert_eq!(
+        run_one_expression("clamp(1.5, 0, 1)"),
+        Ok(Value::Float(1.0))
+    );
+    assert_eq!(
+        run_one_expression("clamp(0.25, 0.0, 1.0)"),
+        Ok(Value::Float(0.25))
+    );
+
+    assert!(run_one_expression("clamp(5, 10, 0)").is_err());
+    assert!(run_one_expression("clamp(true, 0, 10)").is_err());
+}
+
+#[test]
+fn test_join() {
+    assert_eq!(
+        run_one_expression("join([ \"a\", \"b\", \"c\" ], \"-\")"),
+        Ok(Value::String("a-b-c".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("join([ ], \", \")"),
+        Ok(Value::String("".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("join([ \"x\", 1, [ 2 ] ], \" \")"),
+        Ok(Value::String("x 1 [ 2 ]".to_string()))
+    );
+
+    assert!(run_one_expression("join([ \"a\" ], 1)").is_err());
+}
+
+#[test]
+fn test_insert() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                a = [ 1, 2, 3 ]
+                insert(a, 1, 10)
+                insert(a, 4, 20)
+                insert(a, -1, 30)
+                insert(a, 0, [ 0 ])
+                a
+        "}),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(0)]),
+            Value::Integer(1),
+            Value::Integer(10),
+            Value::Integer(2),
+            Value::Integer(3),
+            Value::Integer(30),
+            Value::Integer(20),
+        ]))
+    );
+
+    assert!(run_one_task("task X\n    a = [ 1 ]\n    insert(a, 2, 0)\n").is_err());
+    assert!(run_one_task("task X\n    a = 1\n    insert(a, 0, 0)\n").is_err());
+    assert!(run_one_expression("insert([ 1 ], 0, 0)").is_err());
+}
+
+#[test]
+fn test_remove() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                a = [ 1, 2, 3, 4, 5, 6 ]
+                b = remove(a, 1 .. 3)
+                c = remove(a, -1)
+                d = remove(a, 2 ..)
+                [ a, b, c, d ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(1), Value::Integer(4)]),
+            Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+            Value::Integer(6),
+            Value::Array(vec![Value::Integer(5)]),
+        ]))
+    );
+
+    // Out of range, and the array is left alone
+    assert!(run_one_task("task X\n    a = [ 1, 2 ]\n    remove(a, 2)\n").is_err());
+    assert!(run_one_task("task X\n    a = [ 1, 2 ]\n    remove(a, 1 .. 5)\n").is_err());
+    assert!(run_one_task("task X\n    a = [ 1, 2 ]\n    remove(a, 2 .. 1)\n").is_err());
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                a = [ 1, 2 ]
+                try
+                    remove(a, 5)
+                catch
+                    null
+                a
+        "}),
+        Ok(Value::Array(vec![Value::Integer(1), Value::Integer(2)]))
+    );
+}
+
+#[test]
+fn test_fold() {
+    assert_eq!(run_one_expression("fold([1, 2, 3, 4], 0, acc + x)"), Ok(Value::Integer(10)));
+    assert_eq!(run_one_expression("fold([], 5, acc + x)"), Ok(Value::Integer(5)));
+    assert_eq!(run_one_expression("fold([3, 9, 2], 0, if x > acc then x else acc)"), Ok(Value::Integer(9)));
+
+    // Existing locals with the same names are put back afterwards
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                x = 100
+                total = fold([1, 2], x, acc * x)
+                [ total, x ]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(200), Value::Integer(100)]))
+    );
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(
+        run_one_task(indoc!{r#"
+            task X
+                a = 2
+                b = 3
+                format("{} + {} = {}", a, b, a + b)
+        "#}),
+        Ok(Value::String("2 + 3 = 5".to_string()))
+    );
+    assert_eq!(
+        run_one_expression(r#"format("{{{}}} {}", "x", [1, 2])"#),
+        Ok(Value::String("{x} [ 1, 2 ]".to_string()))
+    );
+
+    assert!(run_one_expression(r#"format("{} {}", 1)"#).is_err());
+    assert!(run_one_expression(r#"format("{}", 1, 2)"#).is_err());
+    assert!(run_one_expression(r#"format("{", 1)"#).is_err());
+}
+
+#[test]
+fn test_index_of() {
+    assert_eq!(run_one_expression("index_of([4, 5, 6, 5], 5)"), Ok(Value::Integer(1)));
+    assert_eq!(run_one_expression("index_of([4, 5, 6], 7)"), Ok(Value::Null));
+    assert_eq!(run_one_expression("index_of([4, 5, 6, 7], e => e > 5)"), Ok(Value::Integer(2)));
+    assert_eq!(run_one_expression("index_of([], e => e > 5)"), Ok(Value::Null));
+
+    // Stops at the first match, so later elements aren't checked
+    assert_eq!(run_one_expression("index_of([1, 0], e => 1 / e == 1)"), Ok(Value::Integer(0)));
+}
+
+#[test]
+fn test_swap() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                arr = [ 1, 2, 3 ]
+                swap(arr, 0, -1)
+                a = 4
+                b = 5
+                swap(a, b)
+                [ arr, a, b ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::Integer(3), Value::Integer(2), Value::Integer(1)]),
+            Value::Integer(5),
+            Value::Integer(4),
+        ]))
+    );
+
+    assert!(run_one_task("task X\n    arr = [ 1, 2 ]\n    swap(arr, 0, 2)\n").is_err());
+    assert!(run_one_task("task X\n    a = 1\n    swap(a, b)\n").is_err());
+}
+
+#[test]
+fn test_to_map_and_pairs() {
+    // Round trips, keeping the order keys were first inserted in
+    assert_eq!(
+        run_one_expression(r#"to_pairs(to_map([["a", 1], ["b", 2], ["a", 3]]))"#),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::String("a".to_string()), Value::Integer(3)]),
+            Value::Array(vec![Value::String("b".to_string()), Value::Integer(2)]),
+        ]))
+    );
+    assert_eq!(
+        run_one_expression(r#"to_map(to_pairs(to_map([[1, true], [2, false]]))) == to_map([[2, false], [1, true]])"#),
+        Ok(Value::Boolean(true))
+    );
+
+    // Maps can be indexed by key, and checked for keys with `in`
+    assert_eq!(run_one_expression(r#"to_map([["x", 10]])["x"]"#), Ok(Value::Integer(10)));
+    assert_eq!(run_one_expression(r#""y" in to_map([["x", 10]])"#), Ok(Value::Boolean(false)));
+    assert_eq!(run_one_expression(r#"to_map([["x", 10], ["y", 20]])"#).unwrap().to_printable_string(), "{ x: 10, y: 20 }");
+
+    assert!(run_one_expression("to_map([[1, 2, 3]])").is_err());
+    assert!(run_one_expression("to_map([[1.5, 2]])").is_err());
+    assert!(run_one_expression("to_pairs([1, 2])").is_err());
+}
+
+#[test]
+fn test_parse_json() {
+    let string = |s: &str| Value::String(s.to_string());
+
+    assert_eq!(
+        run_one_expression(r#"parse_json("[1, -2.5, 1e3, true, null, \"a\\nb\", []]")"#),
+        Ok(Value::Array(vec![
+            Value::Integer(1),
+            Value::Float(-2.5),
+            Value::Float(1000.0),
+            Value::Boolean(true),
+            Value::Null,
+            string("a\nb"),
+            Value::Array(vec![]),
+        ]))
+    );
+
+    // Objects become maps
+    assert_eq!(
+        run_one_expression(r#"parse_json("{ \"name\": \"conker\", \"tags\": [\"x\"], \"nested\": {} }") == to_map([["name", "conker"], ["tags", ["x"]], ["nested", to_map([])]])"#),
+        Ok(Value::Boolean(true))
+    );
+    assert_eq!(
+        run_one_expression(r#"parse_json("{\"a\": {\"b\": 2}}")["a"]["b"]"#),
+        Ok(Value::Integer(2))
+    );
+
+    assert_eq!(
+        run_one_expression(r#"parse_json("[1, 2")"#).unwrap_err().message(),
+        "`parse_json` found malformed JSON: expected `,` but reached the end of input"
+    );
+    assert!(run_one_expression(r#"parse_json("{\"a\" 1}")"#).is_err());
+    assert!(run_one_expression(r#"parse_json("01")"#).is_err());
+    assert!(run_one_expression(r#"parse_json("1 2")"#).is_err());
+
+    // Deep nesting is an error, rather than overflowing the stack
+    let nested = |depth| format!("parse_json(\"{}{}\")", "[".repeat(depth), "]".repeat(depth));
+    assert!(run_one_expression(&nested(200)).is_ok());
+    assert_eq!(
+        run_one_expression(&nested(5000)).unwrap_err().message(),
+        "`parse_json` found malformed JSON: nesting too deep at position 256"
+    );
+}
@@ -0,0 +1,136 @@
+use conker::{bytecode::{Bytecode, Instruction}, node::{Node, NodeKind}, run_code, run_code_with_options, RunOptions};
+use indoc::indoc;
+
+fn assert_same_as_interpreter(input: &str) {
+    let interpreted = run_code(input).unwrap();
+    let compiled = run_code_with_options(input, RunOptions { bytecode: true, ..Default::default() }).unwrap();
+    assert_eq!(interpreted, compiled, "different results for:\n{input}");
+}
+
+#[test]
+fn test_same_results() {
+    let programs = [
+        // Arithmetic, comparisons, and precedence
+        indoc!{"
+            task X
+                [ 1 + 2 * 3, (1 + 2) * 3, 7 / 2, 7.0 / 2, 1 < 2, 2 > 3, 1 == 1.0, [ 1 ] == [ 1 ] ]
+        "},
+
+        // Logical operators, which short-circuit
+        indoc!{"
+            task X
+                [ true && false, false || 1, null || false, false && 1 / 0, true || 1 / 0, 1 < 2 && 2 < 3, not 0, not not null ]
+        "},
+
+        // Errors, rather than panics
+        indoc!{"
+            task X
+                1 / 0
+        "},
+
+        // Locals, conditionals and loops
+        indoc!{"
+            task X
+                total = 0
+                i = 0
+                while i < 100
+                    if i / 2 * 2 == i
+                        total = total + i
+                    i = i + 1
+                [ total, if total > 10 then \"big\" else \"small\", isnull null ]
+        "},
+        indoc!{"
+            task X
+                i = 0
+                while i < 3
+                    i = i + 1
+        "},
+
+        // Anonymous tasks started repeatedly, which share one compiled body
+        indoc!{"
+            task X
+                total = 0
+                for i in 0..5
+                    t = task: x = 3; x * 2
+                    total = total + await t
+                total
+        "},
+
+        // Loops which are left to the interpreter
+        indoc!{"
+            task X
+                found = null
+                outer: while true
+                    i = 0
+                    loop
+                        i = i + 1
+                        if i == 5
+                            continue outer
+                        if i > 3
+                            found = i
+                            break outer
+                found
+        "},
+
+        // Indexing, ranges and builtins
+        indoc!{"
+            task X
+                a = [ 5, 6, 7, 8 ]
+                [ a[1], a[-1], a[1 .. 3], sort(reverse(a)), unique([ 1, 1, 2 ]), (0 .. 10)[4] ]
+        "},
+
+        // Communication
+        indoc!{"
+            task Worker[3]
+                x <- Main
+                x * 2 -> Main
+
+            task Main
+                i = 0
+                results = [ ]
+                while i < 3
+                    i -> Worker[i]
+                    y <- Worker[i]
+                    results = flatten([ results, [ y ] ])
+                    i = i + 1
+                results
+        "},
+
+        // Errors
+        indoc!{"
+            task X
+                x = 1
+                x / 0
+        "},
+        indoc!{"
+            task X
+                try
+                    [ 1 ][3]
+                catch e
+                    e
+        "},
+        indoc!{"
+            task X
+                1 + true
+        "},
+    ];
+
+    for program in programs {
+        assert_same_as_interpreter(program);
+    }
+}
+
+#[test]
+fn test_compiled_loop() {
+    // A loop which only does arithmetic doesn't need the interpreter at all
+    let body = Node::new(NodeKind::While {
+        condition: Box::new(Node::new(NodeKind::BooleanLiteral(true))),
+        body: Box::new(Node::new(NodeKind::Body(vec![
+            Node::new(NodeKind::IntegerLiteral(1)),
+        ]))),
+        label: None,
+    });
+    let bytecode = Bytecode::compile(&body);
+    assert!(!bytecode.instructions.iter().any(|i| matches!(i, Instruction::Evaluate(_))));
+    assert!(bytecode.instructions.iter().any(|i| matches!(i, Instruction::Jump(_))));
+}
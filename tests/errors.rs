@@ -0,0 +1,153 @@
+use conker::{interpreter::{InterpreterErrorKind, Value}, check, run_code};
+use indoc::indoc;
+
+use crate::utils::{run_one_expression, run_one_task};
+
+mod utils;
+
+#[test]
+fn test_division_by_zero() {
+    assert_eq!(run_one_expression("1 / 0").unwrap_err().message(), "division by zero");
+    assert_eq!(run_one_expression("1.0 / 0"), Ok(Value::Float(f64::INFINITY)));
+}
+
+#[test]
+fn test_try_catch() {
+    // The task carries on after the error
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                results = [ ]
+                try
+                    x = 1 / 0
+                    results = [ 1 ]
+                catch e
+                    results = [ e ]
+                [ results, 2 ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Array(vec![Value::String("division by zero".to_string())]),
+            Value::Integer(2),
+        ]))
+    );
+
+    // Without an error, the handler doesn't run - either way, the result is the last statement
+    // which ran
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                try
+                    [ 1, 2 ][1]
+                catch
+                    0
+        "}),
+        Ok(Value::Integer(2))
+    );
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                try
+                    [ 1, 2 ][5]
+                catch
+                    0
+        "}),
+        Ok(Value::Integer(0))
+    );
+
+    // An error in the handler isn't caught
+    assert!(run_one_task(indoc!{"
+        task X
+            try
+                1 / 0
+            catch
+                2 / 0
+    "}).is_err());
+}
+
+#[test]
+fn test_try_catch_closed_channel() {
+    // A closed channel can't be recovered from, so isn't caught
+    let results = run_code(indoc!{"
+        task A
+            1
+
+        task B
+            try
+                x <- A
+            catch
+                0
+    "}).unwrap();
+    assert_eq!(results["B"].as_ref().unwrap_err().kind(), InterpreterErrorKind::ChannelClosed);
+}
+
+#[test]
+fn test_linear_channel() {
+    // `Signal` makes sure both values are waiting before `Sink` receives any
+    let program = |receives: &str| run_code(&format!(indoc!{"
+        task Producer
+            1 -> Sink
+            2 -> Sink
+            null -> Signal
+
+        task Signal
+            _ <- Producer
+            null -> Sink
+
+        task Sink buffer 2 linear
+            _ <- Signal
+            {}
+            x
+    "}, receives)).unwrap();
+
+    assert_eq!(
+        program("x <- Producer").remove("Sink").unwrap().unwrap_err().message(),
+        "finished with 1 unreceived value(s) from Producer on a linear channel"
+    );
+    assert_eq!(program("x <- Producer\n    x <- Producer").remove("Sink").unwrap(), Ok(Value::Integer(2)));
+}
+
+#[test]
+fn test_division_overflow() {
+    let error = run_one_expression("-9223372036854775808 / -1").unwrap_err();
+    assert_eq!(error.kind(), InterpreterErrorKind::IntegerOverflow);
+    assert_eq!(error.message(), "-9223372036854775808 / -1 overflows");
+
+    assert_eq!(run_one_expression("-9223372036854775808 / 1"), Ok(Value::Integer(i64::MIN)));
+}
+
+#[test]
+fn test_integer_overflow() {
+    let error = run_one_expression("9223372036854775807 * 2").unwrap_err();
+    assert_eq!(error.kind(), InterpreterErrorKind::IntegerOverflow);
+    assert_eq!(error.message(), "9223372036854775807 * 2 overflows");
+
+    assert_eq!(run_one_expression("9223372036854775807 + 1").unwrap_err().kind(), InterpreterErrorKind::IntegerOverflow);
+    assert_eq!(run_one_expression("-9223372036854775808 - 1").unwrap_err().kind(), InterpreterErrorKind::IntegerOverflow);
+    assert_eq!(run_one_expression("-(-9223372036854775808)").unwrap_err().message(), "-(-9223372036854775808) overflows");
+
+    // Floats don't overflow, so mixing one in avoids the error
+    assert_eq!(run_one_expression("9223372036854775807 * 2.0"), Ok(Value::Float(i64::MAX as f64 * 2.0)));
+}
+
+#[test]
+fn test_receive_timeout() {
+    let results = run_code(indoc!{"
+        task Silent
+            _ <- Main or null
+
+        task Main
+            try
+                x <- Silent timeout 50
+            catch e
+                e
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::String("receive timed out after 50ms".to_string())));
+
+    // Receives which don't wait can't have a timeout
+    let diagnostics = check(indoc!{"
+        task Main
+            if x <- Main timeout 5000
+                x
+    "});
+    assert_eq!(diagnostics.errors, vec!["a receive in an `if` condition can't have a timeout, since it doesn't wait"]);
+}
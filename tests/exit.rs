@@ -0,0 +1,60 @@
+use indoc::indoc;
+
+use crate::utils::run_binary;
+
+mod utils;
+
+fn run_binary_exit_code(name: &str, input: &str) -> Option<i32> {
+    run_binary(name, input).status.code()
+}
+
+#[test]
+fn test_exit() {
+    assert_eq!(
+        run_binary_exit_code("exit_plain", indoc!{"
+            task X
+                exit
+        "}),
+        Some(0)
+    );
+
+    assert_eq!(
+        run_binary_exit_code("exit_value", indoc!{"
+            task X
+                exit 3
+        "}),
+        Some(3)
+    );
+
+    assert_eq!(
+        run_binary_exit_code("exit_expression", indoc!{"
+            task X
+                x = 2
+                exit x * 10
+        "}),
+        Some(20)
+    );
+
+    // Values which can't be an exit code are a failure
+    assert_eq!(
+        run_binary_exit_code("exit_non_integer", indoc!{"
+            task X
+                exit [ 1, 2 ]
+        "}),
+        Some(1)
+    );
+    assert_eq!(
+        run_binary_exit_code("exit_negative", indoc!{"
+            task X
+                exit -1
+        "}),
+        Some(1)
+    );
+    assert_eq!(
+        run_binary_exit_code("exit_too_big", indoc!{"
+            task X
+                exit 256
+        "}),
+        Some(1)
+    );
+}
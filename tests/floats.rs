@@ -0,0 +1,57 @@
+use conker::interpreter::{Value, FormatOptions, FloatNotation};
+
+use crate::utils::run_one_expression;
+
+mod utils;
+
+fn printed(input: &str) -> String {
+    run_one_expression(input).unwrap().to_printable_string()
+}
+
+#[test]
+fn test_float_arithmetic() {
+    assert_eq!(
+        run_one_expression("1.5 + 2"),
+        Ok(Value::Float(3.5))
+    );
+    assert_eq!(
+        run_one_expression("3 / 2.0"),
+        Ok(Value::Float(1.5))
+    );
+    assert_eq!(
+        run_one_expression("0.5 < 1"),
+        Ok(Value::Boolean(true))
+    );
+
+    // Integers stay integers
+    assert_eq!(
+        run_one_expression("3 / 2"),
+        Ok(Value::Integer(1))
+    );
+}
+
+#[test]
+fn test_float_formatting() {
+    // Floats always look different to integers
+    assert_eq!(printed("1.0"), "1.0");
+    assert_eq!(printed("1"), "1");
+
+    // By default, floats print exactly enough digits to be read back in
+    assert_eq!(printed("0.1 + 0.2"), "0.30000000000000004");
+    assert_eq!(printed("12345678.0 * 100000000000000.0"), "1.2345678e21");
+    assert_eq!(printed("1.0 / 100000.0"), "1e-5");
+
+    let options = FormatOptions { float_precision: Some(2), float_notation: FloatNotation::Auto };
+    assert_eq!(Value::Float(0.1 + 0.2).to_formatted_string(&options), "0.30");
+    assert_eq!(Value::Float(2.0).to_formatted_string(&options), "2.00");
+
+    let options = FormatOptions { float_precision: None, float_notation: FloatNotation::Decimal };
+    assert_eq!(Value::Float(1.5e20).to_formatted_string(&options), "150000000000000000000.0");
+
+    let options = FormatOptions { float_precision: Some(3), float_notation: FloatNotation::Scientific };
+    assert_eq!(Value::Float(1234.5).to_formatted_string(&options), "1.234e3");
+    assert_eq!(
+        Value::Array(vec![Value::Float(1.0), Value::Integer(1)]).to_formatted_string(&options),
+        "[ 1.000e0, 1 ]"
+    );
+}
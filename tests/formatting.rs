@@ -1,6 +1,4 @@
-use std::collections::HashMap;
-
-use conker::{interpreter::Value, run_code};
+use conker::interpreter::Value;
 use indoc::indoc;
 
 use crate::utils::run_one_task;
@@ -0,0 +1,73 @@
+use conker::{interpreter::Value, create_runtime, parse_value};
+use indoc::indoc;
+
+use crate::utils::run_binary_with_args;
+
+mod utils;
+
+#[test]
+fn test_parse_value() {
+    assert_eq!(parse_value("-12"), Some(Value::Integer(-12)));
+    assert_eq!(parse_value("\"hi\""), Some(Value::String("hi".to_string())));
+    assert_eq!(
+        parse_value("[ 1, [ true, null ] ]"),
+        Some(Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(vec![Value::Boolean(true), Value::Null]),
+        ]))
+    );
+
+    // Only literals are allowed
+    assert_eq!(parse_value("1 + 2"), None);
+    assert_eq!(parse_value("x"), None);
+    assert_eq!(parse_value("1 2"), None);
+}
+
+#[test]
+fn test_input() {
+    let mut runtime = create_runtime(indoc!{"
+        task Main
+            x <- $in
+            y <- $in or null
+            [ x, y ]
+    "}).unwrap();
+    runtime.set_input(Value::Integer(5));
+
+    runtime.create_task_channels();
+    runtime.start();
+    assert_eq!(
+        runtime.join()["Main"],
+        Ok(Value::Array(vec![Value::Integer(5), Value::Null]))
+    );
+
+    // With no input, `$in` is closed
+    let mut runtime = create_runtime(indoc!{"
+        task Main
+            x <- $in or 0
+            x
+    "}).unwrap();
+
+    runtime.create_task_channels();
+    runtime.start();
+    assert_eq!(runtime.join()["Main"], Ok(Value::Integer(0)));
+}
+
+#[test]
+fn test_input_from_command_line() {
+    let program = indoc!{"
+        task Main
+            x <- $in or [ 0 ]
+            head(x) * 2 -> $out
+    "};
+
+    let output = run_binary_with_args("input_array", program, &["[21, 1]"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("42\n"));
+
+    let output = run_binary_with_args("input_none", program, &[]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("0\n"));
+
+    let output = run_binary_with_args("input_invalid", program, &["1 +"]);
+    assert!(!output.status.success());
+}
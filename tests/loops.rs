@@ -0,0 +1,183 @@
+use conker::{interpreter::Value, check, run_code};
+use indoc::indoc;
+
+use crate::utils::run_one_task;
+
+mod utils;
+
+#[test]
+fn test_break_continue() {
+    // A `break` nested in an `if` still exits the loop
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                i = 0
+                while true
+                    if i < 5
+                        i = i + 1
+                    else
+                        if i == 5
+                            break
+                        i = i + 100
+                i
+        "}),
+        Ok(Value::Integer(5))
+    );
+
+    // `continue` goes back to checking the condition, which stops the loop here
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                odds = 0
+                i = 0
+                while i < 10
+                    i = i + 1
+                    if (i / 2) * 2 == i
+                        continue
+                    odds = odds + 1
+                [ i, odds ]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(10), Value::Integer(5)]))
+    );
+}
+
+#[test]
+fn test_labeled_break() {
+    // Breaking the outer loop from the inner one stops both
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                count = 0
+                i = 0
+                outer: while i < 3
+                    j = 0
+                    while j < 3
+                        if [ i, j ] == [ 1, 2 ]
+                            break outer
+                        count = count + 1
+                        j = j + 1
+                    i = i + 1
+                [ i, j, count ]
+        "}),
+        Ok(Value::Array(vec![Value::Integer(1), Value::Integer(2), Value::Integer(5)]))
+    );
+
+    // An unlabeled `break` only exits the innermost loop
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                total = 0
+                i = 0
+                outer: while i < 3
+                    i = i + 1
+                    loop
+                        total = total + 1
+                        break
+                total
+        "}),
+        Ok(Value::Integer(3))
+    );
+}
+
+#[test]
+fn test_labeled_continue() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                total = 0
+                i = 0
+                outer: while i < 3
+                    i = i + 1
+                    j = 0
+                    loop
+                        j = j + 1
+                        if j > i
+                            continue outer
+                        total = total + j
+                total
+        "}),
+        // 1 + (1 + 2) + (1 + 2 + 3)
+        Ok(Value::Integer(10))
+    );
+}
+
+#[test]
+fn test_unknown_label() {
+    let diagnostics = check(indoc!{"
+        task X
+            outer: loop
+                break inner
+    "});
+    assert_eq!(diagnostics.errors, vec!["`break` refers to unknown loop label `inner`"]);
+
+    let diagnostics = check(indoc!{"
+        task X
+            continue
+    "});
+    assert_eq!(diagnostics.errors, vec!["`continue` used outside of a loop"]);
+}
+
+#[test]
+fn test_for() {
+    assert_eq!(
+        run_one_task(indoc!{"
+            task X
+                total = 0
+                for x in [ 1, 2, 3 ]
+                    total = total + x
+                up = \"\"
+                for i in 0..4
+                    if i == 1
+                        continue
+                    up = up + i
+                down = \"\"
+                for i in 3..0
+                    down = down + i
+                last = null
+                for i in 10..
+                    last = i
+                    if i == 12
+                        break
+                [ total, up, down, last ]
+        "}),
+        Ok(Value::Array(vec![
+            Value::Integer(6),
+            Value::String("023".to_string()),
+            Value::String("321".to_string()),
+            Value::Integer(12),
+        ]))
+    );
+
+    let error = run_one_task(indoc!{"
+        task X
+            for x in 5
+                x
+    "}).unwrap_err();
+    assert_eq!(error.message(), "`for` expected an array or range, but found 5");
+}
+
+#[test]
+fn test_for_until() {
+    let results = run_code(indoc!{"
+        task Producer
+            1 -> Consumer
+            2 -> Consumer
+            3 -> Consumer
+            -1 -> Consumer
+            4 -> Consumer
+
+        task Consumer
+            total = 0
+            count = 0
+            for x in Producer until -1
+                total = total + x
+                count = count + 1
+            y <- Producer
+            [ total, count, y ]
+    "}).unwrap();
+    assert_eq!(results["Consumer"], Ok(Value::Array(vec![
+        Value::Integer(6),
+        Value::Integer(3),
+        Value::Integer(4),
+    ])));
+}
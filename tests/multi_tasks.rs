@@ -6,6 +6,7 @@ use indoc::indoc;
 mod utils;
 
 #[test]
+#[allow(clippy::identity_op)]
 fn test_multi_task() {
     assert_eq!(
         run_code(indoc!{"
@@ -31,3 +32,477 @@ fn test_multi_task() {
         ]))
     );
 }
+
+#[test]
+fn test_else_if() {
+    assert_eq!(
+        run_code(indoc!{"
+            task Classifier[4]
+                if $index == 0
+                    \"zero\"
+                else if $index == 1
+                    \"one\"
+                else if $index == 2
+                    \"two\"
+                else
+                    \"many\"
+        "}),
+        Some(HashMap::from([
+            ("Classifier[0]".to_string(), Ok(Value::String("zero".to_string()))),
+            ("Classifier[1]".to_string(), Ok(Value::String("one".to_string()))),
+            ("Classifier[2]".to_string(), Ok(Value::String("two".to_string()))),
+            ("Classifier[3]".to_string(), Ok(Value::String("many".to_string()))),
+        ]))
+    );
+}
+
+#[test]
+fn test_receive_from_array() {
+    assert_eq!(
+        run_code(indoc!{"
+            task Worker[3]
+                $index -> Main
+
+            task Main
+                a <- Worker
+                b <- Worker
+                c <- Worker
+                (a + b) + c
+        "}),
+        Some(HashMap::from([
+            ("Worker[0]".to_string(), Ok(Value::Null)),
+            ("Worker[1]".to_string(), Ok(Value::Null)),
+            ("Worker[2]".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Integer(3))),
+        ]))
+    );
+}
+
+#[test]
+fn test_receive_from_array_round_robin() {
+    // The busy loops give the workers time to be ready to send by the time `Main` receives, so
+    // `Main` should take turns between them
+    assert_eq!(
+        run_code(indoc!{"
+            task Worker[3]
+                i = 0
+                while i < 3
+                    $index -> Main
+                    i = i + 1
+
+            task Main
+                received = 0
+                i = 0
+                while i < 9
+                    j = 0
+                    while j < 2000
+                        j = j + 1
+                    x <- Worker
+                    received = (received * 10) + x
+                    i = i + 1
+                received
+        "}),
+        Some(HashMap::from([
+            ("Worker[0]".to_string(), Ok(Value::Null)),
+            ("Worker[1]".to_string(), Ok(Value::Null)),
+            ("Worker[2]".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Integer(12012012))),
+        ]))
+    );
+}
+
+#[test]
+fn test_gather() {
+    // Later instances finish their busy loop first, so send first, but the results are still
+    // ordered by index
+    assert_eq!(
+        run_code(indoc!{"
+            task Worker[3]
+                i = 0
+                while i < ((2 - $index) * 5000)
+                    i = i + 1
+                $index * 10 -> Main
+
+            task Main
+                gather(Worker)
+        "}),
+        Some(HashMap::from([
+            ("Worker[0]".to_string(), Ok(Value::Null)),
+            ("Worker[1]".to_string(), Ok(Value::Null)),
+            ("Worker[2]".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Integer(0),
+                Value::Integer(10),
+                Value::Integer(20),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_yield() {
+    // Each worker counts up, yielding at every step - both should still be able to finish
+    assert_eq!(
+        run_code(indoc!{"
+            task Worker[2]
+                i = 0
+                while i < 100
+                    i = i + 1
+                    yield
+                i -> Main
+
+            task Main
+                gather(Worker)
+        "}),
+        Some(HashMap::from([
+            ("Worker[0]".to_string(), Ok(Value::Null)),
+            ("Worker[1]".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Integer(100),
+                Value::Integer(100),
+            ]))),
+        ]))
+    );
+}
+
+#[test]
+fn test_instance_arguments() {
+    assert_eq!(
+        run_code(indoc!{"
+            const BASE = 10
+
+            task Worker[3] = [ BASE, BASE * 2, \"thirty\" ]
+                $arg -> Main
+
+            task Main
+                gather(Worker)
+        "}),
+        Some(HashMap::from([
+            ("Worker[0]".to_string(), Ok(Value::Null)),
+            ("Worker[1]".to_string(), Ok(Value::Null)),
+            ("Worker[2]".to_string(), Ok(Value::Null)),
+            ("Main".to_string(), Ok(Value::Array(vec![
+                Value::Integer(10),
+                Value::Integer(20),
+                Value::String("thirty".to_string()),
+            ]))),
+        ]))
+    );
+
+    // The initializer must have one element per instance
+    assert_eq!(
+        run_code(indoc!{"
+            task Worker[3] = [ 1, 2 ]
+                $arg
+        "}),
+        None
+    );
+}
+
+#[test]
+fn test_spawn() {
+    let results = run_code(indoc!{"
+        task Worker
+            loop
+                job <- Main or null
+                if job == null
+                    break
+                job * 10 + $index -> Main
+
+        task Main
+            workers = spawn 5 of Worker
+            i = 0
+            while i < 5
+                i -> workers[i]
+                i = i + 1
+            gather(workers)
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Array(vec![
+        Value::Integer(0),
+        Value::Integer(11),
+        Value::Integer(22),
+        Value::Integer(33),
+        Value::Integer(44),
+    ])));
+
+    // The definition still runs as a task too, alongside the spawned instances
+    assert_eq!(results.len(), 7);
+    assert!(results.contains_key("Worker"));
+    assert!(results.contains_key("(spawned Worker 3)[4]"));
+
+    assert!(run_code(indoc!{"
+        task Main
+            spawn 2 of Nothing
+    "}).unwrap()["Main"].is_err());
+}
+
+#[test]
+fn test_inline_task() {
+    assert_eq!(
+        run_code(indoc!{"
+            task Echo: x <- ?c; x -> c
+            task Scaler[2] = [1, 2]: x <- Main; x * $arg -> Main
+
+            task Main
+                5 -> Echo
+                a <- Echo
+                3 -> Scaler[0]
+                3 -> Scaler[1]
+                b <- Scaler[0]
+                c <- Scaler[1]
+                [ a, b + c ]
+        "}).unwrap()["Main"],
+        Ok(Value::Array(vec![Value::Integer(5), Value::Integer(9)]))
+    );
+}
+
+#[test]
+fn test_anonymous_task() {
+    let results = run_code(indoc!{"
+        task Main
+            echo = task: x <- ?c; x * 2 -> c
+            21 -> echo
+            y <- echo
+            y
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Integer(42)));
+
+    // The anonymous task's result is reported too, under a generated name
+    assert_eq!(results.len(), 2);
+    assert!(results.keys().any(|name| name.starts_with("(anonymous")));
+
+    // Each evaluation spawns a new task, and they finish independently of their creator
+    let results = run_code(indoc!{"
+        task Main
+            a = task: x <- ?c; x + 1 -> c
+            b = task: x <- ?c; x + 2 -> c
+            10 -> a
+            10 -> b
+            i <- a
+            j <- b
+            closed = false
+            _ <- a or (closed = true)
+            [ i, j, closed ]
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Array(vec![
+        Value::Integer(11),
+        Value::Integer(12),
+        Value::Boolean(true),
+    ])));
+}
+
+#[test]
+fn test_peers() {
+    let results = run_code(indoc!{"
+        task Listener[3]
+            x <- Broadcaster
+            x + $index
+
+        task Broadcaster
+            peers = $peers
+            loop
+                if peers == [ ]
+                    break
+                10 -> head(peers)
+                peers = tail(peers)
+            $peers == Listener
+    "}).unwrap();
+
+    // Peers are ordered by when their task was defined
+    assert_eq!(results["Broadcaster"], Ok(Value::Boolean(true)));
+    assert_eq!(results["Listener[0]"], Ok(Value::Integer(10)));
+    assert_eq!(results["Listener[1]"], Ok(Value::Integer(11)));
+    assert_eq!(results["Listener[2]"], Ok(Value::Integer(12)));
+}
+
+#[test]
+fn test_send_tagged() {
+    let results = run_code(indoc!{"
+        task Client
+            Add(2, 3) -> Server
+            Negate(4) -> Server
+            Stop() -> Server
+
+        task Server
+            results = [ ]
+            loop
+                msg <- Client
+                if tag(msg) == \"Stop\"
+                    break
+                if tag(msg) == \"Add\"
+                    results = flatten([ results, [ msg[0] + msg[1] ] ])
+                if tag(msg) == \"Negate\"
+                    results = flatten([ results, [ 0 - msg[0] ] ])
+            results
+    "}).unwrap();
+    assert_eq!(results["Server"], Ok(Value::Array(vec![Value::Integer(5), Value::Integer(-4)])));
+}
+
+#[test]
+fn test_await() {
+    let results = run_code(indoc!{"
+        task Main
+            a = task: x = 6; x * 7
+            b = task: y <- ?c; y + 1
+            10 -> b
+            [ await a, await b ]
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Array(vec![Value::Integer(42), Value::Integer(11)])));
+
+    // Errors are passed on to the awaiting task
+    let results = run_code(indoc!{"
+        task Main
+            a = task: 1 / 0
+            try
+                await a
+            catch e
+                e
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::String("division by zero".to_string())));
+
+    // Only a task's own children can be awaited, and only once
+    let results = run_code(indoc!{"
+        task Other
+            1
+
+        task Main
+            await Other
+    "}).unwrap();
+    assert!(results["Main"].is_err());
+    let results = run_code(indoc!{"
+        task Main
+            a = task: 1
+            await a
+            await a
+    "}).unwrap();
+    assert!(results["Main"].is_err());
+}
+
+#[test]
+fn test_barrier() {
+    // No worker can send its second value until every worker has sent its first
+    let results = run_code(indoc!{"
+        task Worker[4]
+            1 -> Collector
+            barrier
+            2 -> Collector
+
+        task Collector
+            results = [ ]
+            i = 0
+            while i < 8
+                x <- Worker
+                results = flatten([ results, [ x ] ])
+                i = i + 1
+            results
+    "}).unwrap();
+    assert_eq!(results["Collector"], Ok(Value::Array(
+        [1, 1, 1, 1, 2, 2, 2, 2].into_iter().map(Value::Integer).collect()
+    )));
+
+    // Tasks which aren't multi-tasks don't wait
+    let results = run_code(indoc!{"
+        task Main
+            barrier
+            1
+    "}).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Integer(1)));
+
+    // An instance which fails before the barrier stops the others from waiting forever
+    let results = run_code(indoc!{"
+        task Worker[2]
+            if $index == 0
+                x = [ ][1]
+            barrier
+            $index
+    "}).unwrap();
+    assert_eq!(results["Worker[0]"].as_ref().unwrap_err().message(), "index 1 is out of range");
+    assert_eq!(results["Worker[1]"].as_ref().unwrap_err().message(), "another instance finished without reaching the barrier");
+}
+
+#[test]
+fn test_ref() {
+    let results = run_code(indoc!{"
+        const LIMIT = 3
+
+        task Adder
+            x <- Router
+            x + 1
+
+        task Doubler[2]
+            x <- Router
+            x * 2
+
+        task Router
+            2 -> ref(\"Adder\")
+            routes = [ \"Doubler\" ]
+            3 -> ref(routes[0])[1]
+            4 -> ref(\"Doubler\")[0]
+            [ ref(\"Adder\") == Adder, ref(\"Missing\"), ref(\"LIMIT\") ]
+    "}).unwrap();
+
+    assert_eq!(results["Adder"], Ok(Value::Integer(3)));
+    assert_eq!(results["Doubler[0]"], Ok(Value::Integer(8)));
+    assert_eq!(results["Doubler[1]"], Ok(Value::Integer(6)));
+    assert_eq!(results["Router"], Ok(Value::Array(vec![Value::Boolean(true), Value::Null, Value::Null])));
+}
+
+#[test]
+fn test_priority() {
+    // `Signal` waits until both values are in `Main`'s buffer, so either could be received first
+    for _ in 0..10 {
+        assert_eq!(
+            run_code(indoc!{"
+                task Low priority -5
+                    1 -> Main
+                    null -> Signal
+
+                task High priority 10
+                    2 -> Main
+                    null -> Signal
+
+                task Signal
+                    _ <- Low
+                    _ <- High
+                    null -> Main
+
+                task Main buffer 1
+                    _ <- Signal
+                    a <- ?c
+                    b <- ?c
+                    [ a, b ]
+            "}).unwrap()["Main"],
+            Ok(Value::Array(vec![Value::Integer(2), Value::Integer(1)]))
+        );
+    }
+}
+
+#[test]
+fn test_notify() {
+    let results = run_code(indoc!{"
+        task Producer notify Consumer
+            i = 1
+            while i < 4
+                i -> Consumer
+                i = i + 1
+
+        task Consumer
+            total = 0
+            for x in Producer until Done(Producer)
+                total = total + x
+            total
+    "}).unwrap();
+    assert_eq!(results["Consumer"], Ok(Value::Integer(6)));
+
+    // Every instance of a multi-task is notified
+    let results = run_code(indoc!{"
+        task Leader notify Followers
+            null
+
+        task Followers[2]
+            x <- Leader
+            x == Done(Leader)
+    "}).unwrap();
+    assert_eq!(results["Followers[0]"], Ok(Value::Boolean(true)));
+    assert_eq!(results["Followers[1]"], Ok(Value::Boolean(true)));
+}
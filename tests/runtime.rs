@@ -0,0 +1,594 @@
+use std::{rc::Rc, cell::RefCell, thread, time::{Duration, Instant}, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, io::Write};
+
+use conker::{clock::MockClock, create_runtime, run_code, run_code_with_options, RunOptions, interpreter::{InterpreterErrorKind, Value}, node::{Node, NodeKind}, runtime::{Runtime, TaskEventKind}};
+use indoc::indoc;
+
+#[test]
+fn test_task_complete_callback() {
+    let mut runtime = create_runtime(indoc!{"
+        task Worker[3]
+            $index -> Main
+
+        task Main
+            a <- Worker[0]
+            b <- Worker[1]
+            c <- Worker[2]
+            a + b + c
+    "}).unwrap();
+
+    let completed = Rc::new(RefCell::new(vec![]));
+    let completed_in_callback = completed.clone();
+    runtime.on_task_complete(move |name, result| {
+        assert!(result.is_ok());
+        completed_in_callback.borrow_mut().push(name.to_string());
+    });
+
+    runtime.create_task_channels();
+    runtime.start();
+    let results = runtime.join();
+
+    let mut completed = completed.borrow().clone();
+    completed.sort();
+    assert_eq!(completed.len(), results.len());
+    assert_eq!(completed, vec!["Main", "Worker[0]", "Worker[1]", "Worker[2]"]);
+}
+
+#[test]
+fn test_busy_wait_warning() {
+    let mut runtime = create_runtime(indoc!{"
+        task Silent
+            _ <- Poller or null
+
+        task Poller
+            i = 0
+            while i < 20
+                x <-? Silent
+                i = i + 1
+    "}).unwrap();
+    runtime.set_busy_wait_warning_threshold(10);
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    assert_eq!(runtime.warnings().len(), 1);
+    assert!(runtime.warnings()[0].starts_with("Poller: 10 failed try-receives in a row"));
+}
+
+#[test]
+fn test_no_busy_wait_warning_by_default() {
+    let mut runtime = create_runtime(indoc!{"
+        task Silent
+            _ <- Poller or null
+
+        task Poller
+            i = 0
+            while i < 20
+                x <-? Silent
+                i = i + 1
+    "}).unwrap();
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    assert!(runtime.warnings().is_empty());
+}
+
+#[test]
+fn test_global_assign_warning() {
+    let mut runtime = create_runtime(indoc!{"
+        const LIMIT = 3
+
+        task Main
+            LIMIT = 5
+            LIMIT = 6
+            x = LIMIT
+    "}).unwrap();
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    // Only the assignment which creates the local warns
+    assert_eq!(runtime.warnings().len(), 1);
+    assert!(runtime.warnings()[0].starts_with("Main: assigning to `LIMIT` creates a local"));
+}
+
+#[test]
+fn test_global_receive_warning() {
+    let mut runtime = create_runtime(indoc!{"
+        const LIMIT = 3
+
+        task Main
+            LIMIT <- B
+
+        task B
+            5 -> Main
+    "}).unwrap();
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    assert_eq!(runtime.warnings().len(), 1);
+    assert!(runtime.warnings()[0].starts_with("Main: assigning to `LIMIT` creates a local"));
+}
+
+#[test]
+fn test_max_evaluation_depth() {
+    let options = RunOptions { max_evaluation_depth: Some(50), ..Default::default() };
+
+    // Each level of parentheses nests another addition
+    let expression = format!("{}1{}", "(1 + ".repeat(100), ")".repeat(100));
+    let results = run_code_with_options(&format!("task Main\n    {expression}\n"), options.clone()).unwrap();
+    let error = results["Main"].as_ref().unwrap_err();
+    assert_eq!(error.kind(), InterpreterErrorKind::RecursionLimit);
+
+    // Shallower code is unaffected
+    let expression = format!("{}1{}", "(1 + ".repeat(10), ")".repeat(10));
+    let results = run_code_with_options(&format!("task Main\n    {expression}\n"), options).unwrap();
+    assert_eq!(results["Main"], Ok(Value::Integer(11)));
+}
+
+#[test]
+fn test_channel_metrics() {
+    let mut runtime = create_runtime(indoc!{"
+        task Producer
+            1 -> Consumer
+            2 -> Consumer
+            3 -> Consumer
+
+        task Consumer
+            loop
+                i = 0
+                while i < 10000
+                    i = i + 1
+                _ <- Producer
+    "}).unwrap();
+    runtime.enable_channel_metrics();
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    let metrics = runtime.channel_metrics();
+    assert_eq!(metrics.len(), 1);
+
+    let producer_to_consumer = &metrics[&("Producer".to_string(), "Consumer".to_string())];
+    assert_eq!(producer_to_consumer.sends, 3);
+    assert!(producer_to_consumer.max_blocked > Duration::ZERO);
+    assert!(producer_to_consumer.total_blocked >= producer_to_consumer.max_blocked);
+}
+
+#[test]
+fn test_run_task_isolated() {
+    let runtime = create_runtime(indoc!{"
+        task Bouncer
+            loop
+                x <- ?c
+                x * 2 -> c
+
+        task Main
+            5 -> Bouncer
+            x <- Bouncer
+            x -> $out
+    "}).unwrap();
+
+    let run = runtime.run_task_isolated("Bouncer", vec![
+        Value::Integer(1),
+        Value::Integer(2),
+        Value::Integer(3),
+    ]).unwrap();
+
+    assert_eq!(run.sent, vec![Value::Integer(2), Value::Integer(4), Value::Integer(6)]);
+
+    // Once the inputs run out, the mock peer has finished, so the loop's receive fails
+    assert!(run.result.is_err());
+
+    assert!(runtime.run_task_isolated("Nonexistent", vec![]).is_none());
+}
+
+#[test]
+fn test_locals_snapshot() {
+    let runtime = create_runtime(indoc!{"
+        task Main
+            total = 1
+            b <- Peer
+            total = total + b
+            a = [ b ]
+
+        task Peer
+            5 -> Main
+    "}).unwrap();
+
+    let run = runtime.run_task_isolated("Main", vec![Value::Integer(5)]).unwrap();
+    assert_eq!(run.locals, vec![
+        ("a".to_string(), Value::Array(vec![Value::Integer(5)])),
+        ("b".to_string(), Value::Integer(5)),
+        ("total".to_string(), Value::Integer(6)),
+    ]);
+}
+
+#[test]
+fn test_discard() {
+    let runtime = create_runtime(indoc!{"
+        task Main
+            _ <- Peer
+            [_, b, _] = [1, 2, 3]
+            _ = b
+
+        task Peer
+            5 -> Main
+    "}).unwrap();
+
+    let run = runtime.run_task_isolated("Main", vec![Value::Integer(5)]).unwrap();
+    assert_eq!(run.locals, vec![
+        ("b".to_string(), Value::Integer(2)),
+    ]);
+}
+
+#[test]
+fn test_memory_limit() {
+    let options = RunOptions { memory_limit: Some(100), ..Default::default() };
+    let results = run_code_with_options(indoc!{"
+        task Huge
+            fill(0, 1000000000000)
+
+        task Nested
+            row = fill(0, 10)
+            fill(row, 10)
+
+        task Growing
+            s = \"ab\"
+            while true
+                s = s + s
+
+        task Repeated
+            while true
+                a = fill(0, 50)
+
+        task Small
+            [fill(0, 10), \"abc\"]
+    "}, options).unwrap();
+
+    for name in ["Huge", "Nested", "Growing", "Repeated"] {
+        assert_eq!(results[name].as_ref().unwrap_err().kind(), InterpreterErrorKind::MemoryLimitExceeded);
+    }
+    assert!(results["Small"].is_ok());
+}
+
+#[test]
+fn test_add_duplicate_task() {
+    let mut runtime = Runtime::new();
+    assert!(runtime.add_task("Foo", Node::new(NodeKind::Body(vec![])), None, None, 0).is_ok());
+    assert!(runtime.add_task("Foo", Node::new(NodeKind::Body(vec![])), None, None, 0).is_err());
+
+    // Constants share the same names
+    assert!(runtime.add_constant("Foo", &Node::new(NodeKind::IntegerLiteral(1))).is_err());
+
+    assert!(run_code(indoc!{"
+        task Foo
+            1
+
+        task Foo
+            2
+    "}).is_none());
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_max_tasks() {
+    let options = RunOptions { max_tasks: Some(100), ..Default::default() };
+    assert!(run_code_with_options("task X[1000000]\n    1\n", options.clone()).is_none());
+
+    // The limit is on the total across all tasks
+    let mut runtime = Runtime::new();
+    runtime.set_max_tasks(3);
+    let body = Node::new(NodeKind::NullLiteral);
+    assert!(runtime.add_task("A", body.clone(), Some(2), None, 0).is_ok());
+    assert_eq!(
+        runtime.add_task("B", body.clone(), Some(2), None, 0).unwrap_err().message(),
+        "cannot create more than 3 tasks"
+    );
+    assert!(runtime.add_task("C", body, None, None, 0).is_ok());
+
+    // Anonymous tasks count too
+    let results = run_code_with_options(indoc!{"
+        task Main
+            a = task: 1
+            b = task: 2
+    "}, RunOptions { max_tasks: Some(2), ..Default::default() }).unwrap();
+    assert_eq!(results["Main"].as_ref().unwrap_err().message(), "cannot create more than 2 tasks");
+}
+
+#[test]
+fn test_message_budget() {
+    let code = indoc!{"
+        task Ping
+            loop
+                1 -> Pong
+                x <- Pong
+
+        task Pong
+            loop
+                x <- Ping
+                x -> Ping
+    "};
+    let results = run_code_with_options(code, RunOptions { message_budget: Some(1000), ..Default::default() }).unwrap();
+
+    // Whichever task went over the budget fails, and the other then finds its channel closed
+    let errors: Vec<_> = results.values().map(|r| r.as_ref().unwrap_err().kind()).collect();
+    assert!(errors.contains(&InterpreterErrorKind::LivelockSuspected));
+
+    // A program which finishes within the budget is unaffected
+    let results = run_code_with_options(indoc!{"
+        task A
+            1 -> B
+            2 -> B
+
+        task B
+            x <- A
+            y <- A
+            x + y
+    "}, RunOptions { message_budget: Some(2), ..Default::default() }).unwrap();
+    assert_eq!(results["B"], Ok(Value::Integer(3)));
+}
+
+#[test]
+fn test_event_sink() {
+    let mut runtime = create_runtime(indoc!{"
+        task Producer
+            5 -> Consumer
+
+        task Consumer
+            x <- Producer
+            x
+    "}).unwrap();
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    runtime.set_event_sink(sender);
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    let mut events: Vec<_> = receiver.try_iter().collect();
+    events.sort_by_key(|event| event.time);
+    let events_for = |task: &str| events.iter()
+        .filter(|event| event.task == task)
+        .map(|event| event.kind.clone())
+        .collect::<Vec<_>>();
+
+    assert_eq!(events_for("Producer"), vec![
+        TaskEventKind::Started,
+        TaskEventKind::Completed(Ok(Value::Null)),
+    ]);
+    assert_eq!(events_for("Consumer"), vec![
+        TaskEventKind::Started,
+        TaskEventKind::BlockedOnReceive { from: Some("Producer".to_string()) },
+        TaskEventKind::Unblocked,
+        TaskEventKind::Completed(Ok(Value::Integer(5))),
+    ]);
+}
+
+#[test]
+fn test_fail_fast() {
+    let mut runtime = create_runtime(indoc!{"
+        task Spinner
+            loop
+                yield
+
+        task Failer
+            1 / 0
+    "}).unwrap();
+    runtime.set_fail_fast(true);
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    runtime.set_event_sink(sender);
+
+    // Without fail-fast, `Spinner` would keep this waiting forever
+    runtime.create_task_channels();
+    runtime.start();
+    let results = runtime.join();
+
+    assert_eq!(results.len(), 1);
+    assert!(results["Failer"].is_err());
+    assert_eq!(runtime.failed_task(), Some("Failer"));
+
+    // `Spinner` stops soon afterwards
+    let spinner_result = receiver.iter()
+        .find_map(|event| match event.kind {
+            TaskEventKind::Completed(result) if event.task == "Spinner" => Some(result),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(spinner_result.unwrap_err().kind(), InterpreterErrorKind::Cancelled);
+}
+
+#[test]
+fn test_output_writer() {
+    let mut runtime = create_runtime(indoc!{"
+        task Worker[3]
+            $index -> Main
+
+        task Main
+            a <- Worker[0]
+            b <- Worker[1]
+            c <- Worker[2]
+            [ a, b, c ] -> $out
+            \"done\" -> $out
+    "}).unwrap();
+
+    let buffer = SharedBuffer::default();
+    runtime.set_output_writer(Box::new(buffer.clone()));
+
+    runtime.create_task_channels();
+    runtime.start();
+    runtime.join();
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(output, "[ 0, 1, 2 ]\ndone\n");
+}
+
+/// A writer whose reader has gone away, like a closed pipe.
+struct BrokenWriter;
+
+impl Write for BrokenWriter {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn test_output_writer_errors() {
+    let mut runtime = create_runtime(indoc!{"
+        task Sender
+            \"hello\" -> $out
+
+        task Flusher
+            flush $out
+
+        task Catcher
+            try
+                \"hello\" -> $out
+                false
+            catch
+                true
+    "}).unwrap();
+    runtime.set_output_writer(Box::new(BrokenWriter));
+
+    runtime.create_task_channels();
+    runtime.start();
+    let results = runtime.join();
+
+    assert_eq!(results["Sender"].as_ref().unwrap_err().kind(), InterpreterErrorKind::OutputError);
+    assert_eq!(results["Flusher"].as_ref().unwrap_err().kind(), InterpreterErrorKind::OutputError);
+    assert_eq!(results["Catcher"], Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_trace_replay() {
+    let code = indoc!{"
+        task Worker[3]
+            $index -> Main
+
+        task Main
+            a <- ?c
+            b <- ?c
+            c <- ?c
+            [ a, b, c ]
+    "};
+
+    let mut runtime = create_runtime(code).unwrap();
+    runtime.record_trace();
+    runtime.create_task_channels();
+    runtime.start();
+    let recorded_result = runtime.join()["Main"].clone();
+    let trace = runtime.trace();
+
+    // Only `Main` made any decisions - one for each receive
+    assert_eq!(trace.events.len(), 1);
+    assert_eq!(trace.events.values().next().unwrap().len(), 3);
+
+    // Whichever order the workers were received from, replaying reproduces it - including after
+    // the trace has been saved as a string
+    let saved_trace = trace.to_string();
+    for _ in 0..10 {
+        let mut runtime = create_runtime(code).unwrap();
+        runtime.replay(saved_trace.parse().unwrap());
+        runtime.create_task_channels();
+        runtime.start();
+        assert_eq!(runtime.join()["Main"], recorded_result);
+    }
+
+    // A trace from a different program doesn't match
+    let mut runtime = create_runtime(indoc!{"
+        task Main
+            x <- ?c
+    "}).unwrap();
+    runtime.replay(trace);
+    runtime.create_task_channels();
+    runtime.start();
+    assert!(runtime.join()["Main"].is_err());
+}
+
+#[test]
+fn test_deadline_before_receive_timeout() {
+    let mut runtime = create_runtime(indoc!{"
+        task Silent
+            _ <- Main or null
+
+        task Main
+            try
+                x <- Silent timeout 10000
+            catch e
+                e
+    "}).unwrap();
+    runtime.set_deadline(Duration::from_millis(100));
+
+    // The deadline stops the receive long before its own timeout, and can't be caught
+    let start = Instant::now();
+    runtime.create_task_channels();
+    runtime.start();
+    let results = runtime.join();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(results["Main"].as_ref().unwrap_err().kind(), InterpreterErrorKind::DeadlineExceeded);
+}
+
+#[test]
+fn test_mock_clock() {
+    let mut runtime = create_runtime(indoc!{"
+        task Silent
+            _ <- Sleeper or null
+            _ <- Main or null
+
+        task Sleeper
+            sleep(60000)
+            \"awake\"
+
+        task Main
+            try
+                x <- Silent timeout 60000
+            catch e
+                e
+    "}).unwrap();
+    let clock = Arc::new(MockClock::new());
+    runtime.set_clock(clock.clone());
+
+    // Time passes a second at a time, much faster than it really would
+    let start = Instant::now();
+    let finished = Arc::new(AtomicBool::new(false));
+    let ticker = {
+        let (clock, finished) = (clock.clone(), finished.clone());
+        thread::spawn(move || while !finished.load(Ordering::SeqCst) {
+            clock.advance(Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(1));
+        })
+    };
+
+    runtime.create_task_channels();
+    runtime.start();
+    let results = runtime.join();
+    finished.store(true, Ordering::SeqCst);
+    ticker.join().unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(results["Main"], Ok(Value::String("receive timed out after 60000ms".to_string())));
+    assert_eq!(results["Sleeper"], Ok(Value::String("awake".to_string())));
+}
@@ -0,0 +1,89 @@
+use conker::interpreter::Value;
+use indoc::indoc;
+
+use crate::utils::{run_one_expression, run_one_task, run_binary};
+
+mod utils;
+
+#[test]
+fn test_string_literal() {
+    assert_eq!(
+        run_one_expression("\"hello\""),
+        Ok(Value::String("hello".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("\"\""),
+        Ok(Value::String("".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("\"a\" == \"a\""),
+        Ok(Value::Boolean(true))
+    );
+
+    // Even an empty string is truthy
+    assert_eq!(
+        run_one_expression("if \"\" then 1 else 2"),
+        Ok(Value::Integer(1))
+    );
+    assert_eq!(
+        run_one_expression(r#""tab\there""#),
+        Ok(Value::String("tab\there".to_string()))
+    );
+}
+
+#[test]
+fn test_multiline_string() {
+    assert_eq!(
+        run_one_task(indoc!{r#"
+            task X
+                x = """
+                one
+                  two
+                three
+                """
+                x
+        "#}),
+        Ok(Value::String("one\n  two\nthree".to_string()))
+    );
+
+    // Indentation is only stripped where it's common to every line
+    assert_eq!(
+        run_one_task(indoc!{r#"
+            task X
+                """  a
+              b
+            c"""
+        "#}),
+        Ok(Value::String("  a\n  b\nc".to_string()))
+    );
+
+    let output = run_binary("multiline_string", indoc!{r#"
+        task X
+            """
+            one
+              two
+            three
+            """ -> $out
+    "#});
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("one\n  two\nthree\n"));
+}
+
+#[test]
+fn test_string_concatenation() {
+    assert_eq!(
+        run_one_expression("\"foo\" + \"bar\""),
+        Ok(Value::String("foobar".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("\"count: \" + 3 + \", done: \" + true"),
+        Ok(Value::String("count: 3, done: true".to_string()))
+    );
+    assert_eq!(
+        run_one_expression("1.5 + \"x\""),
+        Ok(Value::String("1.5x".to_string()))
+    );
+
+    let error = run_one_expression("\"x\" + [1]").unwrap_err();
+    assert_eq!(error.message(), "cannot add x and [ 1 ] - only strings, numbers, and booleans can be joined into a string");
+    assert!(run_one_expression("\"x\" - 1").is_err());
+}
@@ -0,0 +1,165 @@
+use std::io;
+
+use conker::tokenizer::{Tokenizer, TokenKind, TokenizerError};
+
+fn tokenize(input: &str) -> (Vec<TokenKind>, Vec<TokenizerError>) {
+    let input_chars: Vec<_> = input.chars().collect();
+    let mut tokenizer = Tokenizer::new(&input_chars);
+    tokenizer.tokenize();
+
+    (tokenizer.tokens.into_iter().map(|t| t.kind).collect(), tokenizer.errors)
+}
+
+#[test]
+fn test_integer_literals() {
+    let (tokens, errors) = tokenize("123, -45, 9223372036854775807, -9223372036854775808");
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        TokenKind::IntegerLiteral(123),
+        TokenKind::Comma,
+        TokenKind::IntegerLiteral(-45),
+        TokenKind::Comma,
+        TokenKind::IntegerLiteral(i64::MAX),
+        TokenKind::Comma,
+        TokenKind::IntegerLiteral(i64::MIN),
+        TokenKind::EndOfFile,
+    ]);
+
+    // Too big to fit - this is reported, and tokenizing continues
+    let (tokens, errors) = tokenize("123456789012345678901234567890 + 1");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "integer literal out of range");
+    assert_eq!(tokens, vec![
+        TokenKind::Add,
+        TokenKind::IntegerLiteral(1),
+        TokenKind::EndOfFile,
+    ]);
+}
+
+#[test]
+fn test_float_literals() {
+    let (tokens, errors) = tokenize("2.75 1..2 1.5..2.5");
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        TokenKind::FloatLiteral(2.75),
+        TokenKind::IntegerLiteral(1), TokenKind::Range, TokenKind::IntegerLiteral(2),
+        TokenKind::FloatLiteral(1.5), TokenKind::Range, TokenKind::FloatLiteral(2.5),
+        TokenKind::EndOfFile,
+    ]);
+
+    let (_, errors) = tokenize("1.2.3");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "malformed number `1.2.3`");
+}
+
+#[test]
+fn test_string_literals() {
+    let (tokens, errors) = tokenize(r#""a\tb\n" "say \"hi\"" "back\\slash" """#);
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        TokenKind::StringLiteral("a\tb\n".to_string()),
+        TokenKind::StringLiteral("say \"hi\"".to_string()),
+        TokenKind::StringLiteral("back\\slash".to_string()),
+        TokenKind::StringLiteral("".to_string()),
+        TokenKind::EndOfFile,
+    ]);
+
+    let (_, errors) = tokenize("x = 1\ny = \"oops\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "unterminated string (line 2, column 5)");
+
+    let (_, errors) = tokenize("x = 1\ny = \"\"\"\n    oops\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "unterminated multi-line string (line 2, column 5)");
+
+    let (_, errors) = tokenize(r#""\q" + 1"#);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), r"unknown escape sequence `\q` (line 1, column 2)");
+}
+
+#[test]
+fn test_negative_literals() {
+    let id = |name: &str| TokenKind::Identifier(name.to_string());
+
+    // After anything which could be subtracted from, `-` is a subtraction
+    let (tokens, errors) = tokenize("3-1 x -1 (x)-1 [-1, -2]");
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        TokenKind::IntegerLiteral(3), TokenKind::Subtract, TokenKind::IntegerLiteral(1),
+        id("x"), TokenKind::Subtract, TokenKind::IntegerLiteral(1),
+        TokenKind::LeftParen, id("x"), TokenKind::RightParen, TokenKind::Subtract, TokenKind::IntegerLiteral(1),
+        TokenKind::LeftBrace, TokenKind::IntegerLiteral(-1), TokenKind::Comma, TokenKind::IntegerLiteral(-2), TokenKind::RightBrace,
+        TokenKind::EndOfFile,
+    ]);
+}
+
+#[test]
+fn test_mixed_indentation() {
+    let (_, errors) = tokenize("task X\n  \t1\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "mixed tabs and spaces in indentation (column 3)");
+
+    let (_, errors) = tokenize("task X\n\t\t \t1\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "mixed tabs and spaces in indentation (column 3)");
+}
+
+#[test]
+fn test_comparisons_and_arrows() {
+    let id = |name: &str| TokenKind::Identifier(name.to_string());
+
+    let (tokens, errors) = tokenize("a <- b <= c < d -> e >= f > g != h == i");
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        id("a"), TokenKind::ReceiveArrow,
+        id("b"), TokenKind::LessThanOrEqual,
+        id("c"), TokenKind::LessThan,
+        id("d"), TokenKind::SendArrow,
+        id("e"), TokenKind::GreaterThanOrEqual,
+        id("f"), TokenKind::GreaterThan,
+        id("g"), TokenKind::NotEquals,
+        id("h"), TokenKind::Equals,
+        id("i"),
+        TokenKind::EndOfFile,
+    ]);
+
+    // Without spaces, arrows win over comparisons with negative numbers, but not over `<=`
+    let (tokens, errors) = tokenize("a<-1 a<=-1 a->?b a>=1 a<-?b");
+    assert!(errors.is_empty());
+    assert_eq!(tokens, vec![
+        id("a"), TokenKind::ReceiveArrow, TokenKind::IntegerLiteral(1),
+        id("a"), TokenKind::LessThanOrEqual, TokenKind::IntegerLiteral(-1),
+        id("a"), TokenKind::TrySendArrow, id("b"),
+        id("a"), TokenKind::GreaterThanOrEqual, TokenKind::IntegerLiteral(1),
+        id("a"), TokenKind::TryReceiveArrow, id("b"),
+        TokenKind::EndOfFile,
+    ]);
+
+    // A lone `!` isn't an operator
+    let (_, errors) = tokenize("a ! b");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_tokenize_reader() {
+    let input = indoc::indoc!{r#"
+        task Main
+            x = """
+                spans "lines"
+            """
+            [ x, -1.5, 3-1 ] -> $out
+    "#};
+
+    let mut tokenizer = Tokenizer::from_reader(io::BufReader::with_capacity(4, input.as_bytes()));
+    tokenizer.tokenize();
+    assert!(tokenizer.errors.is_empty());
+
+    let (tokens, _) = tokenize(input);
+    assert_eq!(tokenizer.tokens.into_iter().map(|t| t.kind).collect::<Vec<_>>(), tokens);
+
+    // Failing to read is reported as an error
+    let mut tokenizer = Tokenizer::from_reader(io::BufReader::new(&[b'1', b' ', 0xFF][..]));
+    tokenizer.tokenize();
+    assert_eq!(tokenizer.errors.len(), 1);
+    assert!(tokenizer.errors[0].message().starts_with("could not read input"));
+}
@@ -1,3 +1,7 @@
+#![allow(dead_code)]
+
+use std::{process::{Command, Output}, fs, env};
+
 use conker::{interpreter::{Value, InterpreterError}, run_code};
 
 pub fn run_one_task(input: &str) -> Result<Value, InterpreterError> {
@@ -7,3 +11,21 @@ pub fn run_one_task(input: &str) -> Result<Value, InterpreterError> {
 pub fn run_one_expression(input: &str) -> Result<Value, InterpreterError> {
     run_one_task(&format!("task X\n    {input}\n"))
 }
+
+pub fn run_binary(name: &str, input: &str) -> Output {
+    run_binary_with_args(name, input, &[])
+}
+
+pub fn run_binary_with_args(name: &str, input: &str, args: &[&str]) -> Output {
+    let path = env::temp_dir().join(format!("conker_test_{name}.ckr"));
+    fs::write(&path, input).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_conker"))
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    output
+}